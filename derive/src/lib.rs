@@ -0,0 +1,95 @@
+//! `#[derive(Commute)]` for structs of `qsv-stats` summaries.
+//!
+//! Enable the `derive` feature of `qsv-stats` instead of depending on this
+//! crate directly, and import the macro with `use stats::Commute;`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Index, LitStr, Path};
+
+/// Derives `Commute` for a struct by merging each field with its own
+/// `Commute` implementation.
+///
+/// Every field must implement `Commute`. As with hand-written impls, the
+/// struct's `Default` should be the identity of `merge`, which it is when
+/// `Default` is derived too.
+///
+/// The impl refers to the trait as `::stats::Commute`. If `qsv-stats` is
+/// renamed in `Cargo.toml`, or re-exported from another crate, set the path
+/// with `#[commute(crate = "path")]` on the struct.
+#[proc_macro_derive(Commute, attributes(commute))]
+pub fn derive_commute(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            "Commute can only be derived for structs",
+        ));
+    };
+
+    let krate = crate_path(input)?;
+    let merges: Vec<TokenStream2> = match &data.fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|f| {
+                let name = &f.ident;
+                quote!(#krate::Commute::merge(&mut self.#name, other.#name);)
+            })
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                quote!(#krate::Commute::merge(&mut self.#index, other.#index);)
+            })
+            .collect(),
+        Fields::Unit => vec![quote!(let _ = other;)],
+    };
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for field in &data.fields {
+        let ty = &field.ty;
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: #krate::Commute));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::Commute for #name #ty_generics #where_clause {
+            #[inline]
+            fn merge(&mut self, other: Self) {
+                #(#merges)*
+            }
+        }
+    })
+}
+
+/// Returns the path of the `qsv-stats` crate, from `#[commute(crate = "...")]`
+/// if it is given.
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+    let mut krate = syn::parse_quote!(::stats);
+    for attr in &input.attrs {
+        if !attr.path().is_ident("commute") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported commute attribute"))
+            }
+        })?;
+    }
+    Ok(krate)
+}
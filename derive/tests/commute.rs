@@ -0,0 +1,79 @@
+use stats::{merge_all, Commute, Frequencies, MinMax, OnlineStats, Unsorted};
+
+#[derive(Default, Commute)]
+struct Column {
+    online: OnlineStats,
+    minmax: MinMax<i64>,
+    unsorted: Unsorted<i64>,
+    freqs: Frequencies<i64>,
+}
+
+impl Column {
+    fn from_slice(data: &[i64]) -> Column {
+        Column {
+            online: OnlineStats::from_slice(data),
+            minmax: data.iter().copied().collect(),
+            unsorted: data.iter().copied().collect(),
+            freqs: data.iter().copied().collect(),
+        }
+    }
+}
+
+#[derive(Default, Commute)]
+struct Pair<T: PartialOrd + Clone>(MinMax<T>, Option<MinMax<T>>);
+
+#[derive(Default, Commute)]
+struct Nothing;
+
+/// A stand-in for a renamed crate, with its own `Commute`, so the derive
+/// only compiles if it uses the path it is given.
+mod shim {
+    pub trait Commute {
+        fn merge(&mut self, other: Self);
+    }
+
+    impl Commute for u32 {
+        fn merge(&mut self, other: u32) {
+            *self += other;
+        }
+    }
+
+    #[derive(Default, stats::Commute)]
+    #[commute(crate = "crate::shim")]
+    pub struct Total(pub u32);
+}
+
+#[test]
+fn named_fields() {
+    let data = [4, 1, 7, 1, 3, 9, 2];
+    let mut merged = merge_all(data.chunks(3).map(Column::from_slice)).unwrap();
+    merged.merge(Column::default());
+    let single = Column::from_slice(&data);
+
+    assert_eq!(merged.online.len(), single.online.len());
+    assert!((merged.online.mean() - single.online.mean()).abs() < 1e-12);
+    assert_eq!(merged.minmax, single.minmax);
+    assert_eq!(merged.unsorted.median(), single.unsorted.clone().median());
+    assert_eq!(merged.freqs.count(&1), 2);
+}
+
+#[test]
+fn tuple_fields() {
+    let mut pair: Pair<f64> = Pair(vec![2.0].into_iter().collect(), None);
+    pair.merge(Pair(
+        vec![-1.0].into_iter().collect(),
+        Some(vec![5.0].into_iter().collect()),
+    ));
+    assert_eq!(pair.0.min(), Some(&-1.0));
+    assert_eq!(pair.1.unwrap().max(), Some(&5.0));
+
+    let mut nothing = Nothing;
+    nothing.merge(Nothing);
+}
+
+#[test]
+fn crate_path() {
+    let mut total = shim::Total(3);
+    shim::Commute::merge(&mut total, shim::Total(8));
+    assert_eq!(total.0, 11);
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use serde::de::{Deserializer, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{hash64, Commute};
+
+/// The default relative error of a count, as a fraction of the total count.
+const DEFAULT_EPSILON: f64 = 0.001;
+
+/// The default probability that a count exceeds the error bound.
+const DEFAULT_DELTA: f64 = 0.01;
+
+/// A commutative data structure for approximate frequency counts in bounded
+/// memory.
+///
+/// This is the Count-Min sketch from Cormode, G. and Muthukrishnan, S.
+/// (2005), "An improved data stream summary: the count-min sketch and its
+/// applications". Counts never underestimate the true count, and with
+/// probability at least `1 - delta()` they overestimate it by at most
+/// `epsilon() * len()`.
+///
+/// With conservative update (see `conservative`), only the smallest counters
+/// for a value are incremented, which tightens the estimates further but
+/// means the sketch only supports increments.
+///
+/// Values are hashed with a fixed-key hasher, so sketches can be serialized
+/// and merged across threads, processes and files, as long as they share
+/// the same dimensions and were built with the same Rust toolchain and target.
+#[derive(Clone, Serialize, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct CountMinSketch<T> {
+    width: usize,
+    depth: usize,
+    conservative: bool,
+    total: u64,
+    counters: Vec<u64>,
+    #[serde(skip)]
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash> CountMinSketch<T> {
+    /// Create an empty sketch whose counts are within `epsilon * len()` of
+    /// the true count with probability at least `1 - delta`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epsilon` or `delta` is not in `(0, 1)`.
+    #[must_use]
+    pub fn new(epsilon: f64, delta: f64) -> CountMinSketch<T> {
+        assert!(
+            epsilon > 0.0 && epsilon < 1.0,
+            "Count-Min epsilon must be between 0 and 1"
+        );
+        assert!(
+            delta > 0.0 && delta < 1.0,
+            "Count-Min delta must be between 0 and 1"
+        );
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        CountMinSketch::with_dimensions(width, depth)
+    }
+
+    /// Create an empty sketch with `depth` rows of `width` counters each.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` or `depth` is `0`.
+    #[must_use]
+    pub fn with_dimensions(width: usize, depth: usize) -> CountMinSketch<T> {
+        assert!(
+            width > 0 && depth > 0,
+            "Count-Min dimensions must be positive"
+        );
+        CountMinSketch {
+            width,
+            depth,
+            conservative: false,
+            total: 0,
+            counters: vec![0; width * depth],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Enable conservative update for subsequent samples.
+    #[must_use]
+    pub const fn conservative(mut self) -> CountMinSketch<T> {
+        self.conservative = true;
+        self
+    }
+
+    /// Add a sample to the sketch.
+    #[inline]
+    pub fn add(&mut self, v: T) {
+        self.total += 1;
+        let cells = self.cells(&v);
+        if self.conservative {
+            let min = cells.clone().map(|i| self.counters[i]).min().unwrap_or(0);
+            for i in cells {
+                if self.counters[i] == min {
+                    self.counters[i] += 1;
+                }
+            }
+        } else {
+            for i in cells {
+                self.counters[i] += 1;
+            }
+        }
+    }
+
+    /// Return the estimated number of occurrences of `v` in the data.
+    ///
+    /// This never underestimates the true count.
+    #[inline]
+    #[must_use]
+    pub fn count(&self, v: &T) -> u64 {
+        self.cells(v).map(|i| self.counters[i]).min().unwrap_or(0)
+    }
+
+    /// Returns the relative error guarantee, i.e. counts overestimate by at
+    /// most `epsilon() * len()` with probability `1 - delta()`.
+    #[inline]
+    #[must_use]
+    pub fn epsilon(&self) -> f64 {
+        std::f64::consts::E / self.width as f64
+    }
+
+    /// Returns the probability that a count exceeds the error guarantee.
+    #[inline]
+    #[must_use]
+    pub fn delta(&self) -> f64 {
+        (-(self.depth as f64)).exp()
+    }
+
+    /// Returns the number of counters per row.
+    #[inline]
+    #[must_use]
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows.
+    #[inline]
+    #[must_use]
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.total as usize
+    }
+
+    /// Returns true if there are no data points.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// The index of `v`'s counter in each row.
+    ///
+    /// The row hashes are derived from two base hashes, as described in
+    /// Kirsch, A. and Mitzenmacher, M. (2006), "Less Hashing, Same
+    /// Performance".
+    #[inline]
+    fn cells(&self, v: &T) -> impl Iterator<Item = usize> + Clone {
+        let (width, depth) = (self.width, self.depth);
+        let h1 = hash64(v, 0);
+        let h2 = hash64(v, 1);
+        (0..depth).map(move |row| {
+            let h = h1.wrapping_add((row as u64).wrapping_mul(h2));
+            row * width + (h % width as u64) as usize
+        })
+    }
+}
+
+impl<T: Hash> Commute for CountMinSketch<T> {
+    #[inline]
+    fn merge(&mut self, v: CountMinSketch<T>) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            let conservative = self.conservative || v.conservative;
+            *self = v;
+            self.conservative = conservative;
+            return;
+        }
+        assert!(
+            self.width == v.width && self.depth == v.depth,
+            "cannot merge Count-Min sketches with different dimensions"
+        );
+        self.total += v.total;
+        for (c1, c2) in self.counters.iter_mut().zip(v.counters) {
+            *c1 += c2;
+        }
+    }
+}
+
+/// The serialized form of `CountMinSketch`, which is validated before use.
+#[derive(Deserialize)]
+struct RawCountMinSketch {
+    width: usize,
+    depth: usize,
+    conservative: bool,
+    total: u64,
+    counters: Vec<u64>,
+}
+
+impl<'de, T> Deserialize<'de> for CountMinSketch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawCountMinSketch::deserialize(deserializer)?;
+        let (width, depth) = (raw.width, raw.depth);
+        if width == 0 || depth == 0 || width.checked_mul(depth) != Some(raw.counters.len()) {
+            return Err(D::Error::custom(
+                "Count-Min sketch must have positive dimensions and width * depth counters",
+            ));
+        }
+        Ok(CountMinSketch {
+            width,
+            depth,
+            conservative: raw.conservative,
+            total: raw.total,
+            counters: raw.counters,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Hash> Default for CountMinSketch<T> {
+    #[inline]
+    fn default() -> CountMinSketch<T> {
+        CountMinSketch::new(DEFAULT_EPSILON, DEFAULT_DELTA)
+    }
+}
+
+impl<T> fmt::Debug for CountMinSketch<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CountMinSketch({}x{}, n={})",
+            self.depth, self.width, self.total
+        )
+    }
+}
+
+impl<T: Hash> FromIterator<T> for CountMinSketch<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> CountMinSketch<T> {
+        let mut v = CountMinSketch::default();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Hash> Extend<T> for CountMinSketch<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CountMinSketch;
+    use crate::{Commute, Frequencies};
+
+    fn data() -> Vec<u64> {
+        (0..20_000u64).map(|i| (i * i) % 1_009).collect()
+    }
+
+    #[test]
+    fn countmin_bounds() {
+        let exact: Frequencies<u64> = data().into_iter().collect();
+        let mut plain = CountMinSketch::new(0.01, 0.001);
+        let mut conservative = CountMinSketch::new(0.01, 0.001).conservative();
+        plain.extend(data());
+        conservative.extend(data());
+
+        assert_eq!(plain.width(), 272);
+        assert_eq!(plain.depth(), 7);
+        assert!(plain.epsilon() <= 0.01);
+        assert!(plain.delta() <= 0.001);
+
+        let bound = (plain.epsilon() * plain.len() as f64) as u64;
+        for v in 0..1_100 {
+            let truth = exact.count(&v);
+            let (p, c) = (plain.count(&v), conservative.count(&v));
+            assert!(truth <= c && c <= p, "{v}: {truth} {c} {p}");
+            assert!(p - truth <= bound);
+        }
+    }
+
+    #[test]
+    fn countmin_merge() {
+        let data = data();
+        let (left, right) = data.split_at(7_000);
+        let mut merged: CountMinSketch<u64> = left.iter().copied().collect();
+        merged.merge(right.iter().copied().collect());
+        let single: CountMinSketch<u64> = data.into_iter().collect();
+        assert_eq!(merged, single);
+
+        merged.merge(CountMinSketch::default());
+        assert_eq!(merged, single);
+    }
+
+    #[test]
+    fn countmin_serde() {
+        let mut sketch = CountMinSketch::with_dimensions(4, 3).conservative();
+        sketch.extend(["a", "b", "a"]);
+        let json = serde_json::to_string(&sketch).unwrap();
+        let back: CountMinSketch<&str> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, sketch);
+        assert_eq!(back.count(&"a"), 2);
+
+        for json in [
+            r#"{"width":4,"depth":3,"conservative":false,"total":0,"counters":[]}"#,
+            r#"{"width":0,"depth":3,"conservative":false,"total":0,"counters":[]}"#,
+            r#"{"width":2,"depth":0,"conservative":false,"total":0,"counters":[]}"#,
+            r#"{"width":1,"depth":2,"conservative":false,"total":0,"counters":[0,0,0]}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<CountMinSketch<u64>>(json).is_err(),
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "different dimensions")]
+    fn countmin_merge_mismatch() {
+        let mut a = CountMinSketch::with_dimensions(10, 2);
+        a.add("a");
+        let mut b = CountMinSketch::with_dimensions(20, 2);
+        b.add("b");
+        a.merge(b);
+    }
+}
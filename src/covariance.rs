@@ -0,0 +1,578 @@
+use std::fmt;
+
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::{Commute, OnlineStats};
+
+/// Online state for computing the covariance and correlation of pairs of
+/// values, and the least-squares line through them.
+///
+/// This uses the co-moment form of Welford's algorithm, so it is accurate
+/// even when the means are large compared to the spread of the data.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct OnlineCovariance {
+    size: u64,
+    mean_x: f64,
+    mean_y: f64,
+    /// Sum of squared differences from the mean of `x`.
+    qx: f64,
+    /// Sum of squared differences from the mean of `y`.
+    qy: f64,
+    /// Sum of products of differences from the means of `x` and `y`.
+    cxy: f64,
+}
+
+impl OnlineCovariance {
+    /// Create initial state.
+    #[must_use]
+    pub fn new() -> OnlineCovariance {
+        Default::default()
+    }
+
+    /// Add a new pair of samples.
+    #[inline]
+    pub fn add<X: ToPrimitive, Y: ToPrimitive>(&mut self, x: &X, y: &Y) {
+        let (x, y) = (x.to_f64().unwrap(), y.to_f64().unwrap());
+        // Taken from: https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Online
+        self.size += 1;
+        let n = self.size as f64;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / n;
+        self.mean_y += dy / n;
+        self.cxy += dx * (y - self.mean_y);
+        self.qx += dx * (x - self.mean_x);
+        self.qy += dy * (y - self.mean_y);
+    }
+
+    /// Return the current mean of `x`.
+    #[must_use]
+    pub const fn mean_x(&self) -> f64 {
+        self.mean_x
+    }
+
+    /// Return the current mean of `y`.
+    #[must_use]
+    pub const fn mean_y(&self) -> f64 {
+        self.mean_y
+    }
+
+    /// Return the current population covariance (divided by `n`).
+    #[must_use]
+    pub fn population_covariance(&self) -> f64 {
+        self.cxy / (self.size as f64)
+    }
+
+    /// Return the current sample covariance (divided by `n - 1`).
+    ///
+    /// This is the Bessel-corrected estimator, e.g. what `COVARIANCE.S`
+    /// returns in spreadsheets. It is `NaN` when there are fewer than two
+    /// samples.
+    #[must_use]
+    pub fn sample_covariance(&self) -> f64 {
+        if self.size < 2 {
+            return f64::NAN;
+        }
+        self.cxy / ((self.size - 1) as f64)
+    }
+
+    /// Return the current Pearson correlation coefficient.
+    ///
+    /// This is `NaN` if either `x` or `y` is constant.
+    #[must_use]
+    pub fn pearson(&self) -> f64 {
+        let r = self.cxy / (self.qx * self.qy).sqrt();
+        // Guard against rounding pushing `r` just outside of `[-1, 1]`.
+        r.clamp(-1.0, 1.0)
+    }
+
+    /// Return the slope of the least-squares line `y = intercept + slope * x`.
+    ///
+    /// This is `NaN` if `x` is constant.
+    #[must_use]
+    pub fn slope(&self) -> f64 {
+        self.cxy / self.qx
+    }
+
+    /// Return the intercept of the least-squares line
+    /// `y = intercept + slope * x`.
+    ///
+    /// This is `NaN` if `x` is constant.
+    #[must_use]
+    pub fn intercept(&self) -> f64 {
+        (-self.slope()).mul_add(self.mean_x, self.mean_y)
+    }
+
+    /// Return the coefficient of determination of the least-squares line.
+    ///
+    /// For simple linear regression, this is the square of `pearson`.
+    #[must_use]
+    pub fn r_squared(&self) -> f64 {
+        let r = self.pearson();
+        r * r
+    }
+
+    /// Returns the number of pairs.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns if empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl Commute for OnlineCovariance {
+    #[inline]
+    fn merge(&mut self, v: OnlineCovariance) {
+        if v.size == 0 {
+            return;
+        }
+        if self.size == 0 {
+            *self = v;
+            return;
+        }
+
+        // Taken from: Pébay (2008), "Formulas for Robust, One-Pass Parallel
+        // Computation of Covariances and Arbitrary-Order Statistical Moments"
+        let (s1, s2) = (self.size as f64, v.size as f64);
+        let n = s1 + s2;
+        let dx = v.mean_x - self.mean_x;
+        let dy = v.mean_y - self.mean_y;
+        let f = s1 * s2 / n;
+        self.cxy += (dx * dy).mul_add(f, v.cxy);
+        self.qx += (dx * dx).mul_add(f, v.qx);
+        self.qy += (dy * dy).mul_add(f, v.qy);
+        self.mean_x = s1.mul_add(self.mean_x, s2 * v.mean_x) / n;
+        self.mean_y = s1.mul_add(self.mean_y, s2 * v.mean_y) / n;
+        self.size += v.size;
+    }
+}
+
+impl Default for OnlineCovariance {
+    fn default() -> OnlineCovariance {
+        OnlineCovariance {
+            size: 0,
+            mean_x: 0.0,
+            mean_y: 0.0,
+            qx: 0.0,
+            qy: 0.0,
+            cxy: 0.0,
+        }
+    }
+}
+
+impl fmt::Debug for OnlineCovariance {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cov={:.10} r={:.10}",
+            self.population_covariance(),
+            self.pearson()
+        )
+    }
+}
+
+impl<X: ToPrimitive, Y: ToPrimitive> FromIterator<(X, Y)> for OnlineCovariance {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (X, Y)>>(it: I) -> OnlineCovariance {
+        let mut v = OnlineCovariance::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<X: ToPrimitive, Y: ToPrimitive> Extend<(X, Y)> for OnlineCovariance {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (X, Y)>>(&mut self, it: I) {
+        for (x, y) in it {
+            self.add(&x, &y);
+        }
+    }
+}
+
+/// Online state for computing the covariance and correlation matrices of
+/// rows of values.
+///
+/// Each row must have the same number of values (the dimension). The full
+/// co-moment matrix is kept, so this uses `O(dimension^2)` space and each row
+/// takes `O(dimension^2)` time to add.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct CovarianceMatrix {
+    dimension: usize,
+    size: u64,
+    means: Vec<f64>,
+    /// The upper triangle (including the diagonal) of the co-moment matrix,
+    /// row by row.
+    comoments: Vec<f64>,
+    columns: Vec<OnlineStats>,
+}
+
+impl CovarianceMatrix {
+    /// Create initial state for rows of `dimension` values.
+    #[must_use]
+    pub fn new(dimension: usize) -> CovarianceMatrix {
+        CovarianceMatrix {
+            dimension,
+            size: 0,
+            means: vec![0.0; dimension],
+            comoments: vec![0.0; dimension * (dimension + 1) / 2],
+            columns: vec![OnlineStats::new(); dimension],
+        }
+    }
+
+    /// Add a new row of samples.
+    ///
+    /// If the state was created with `Default::default()` and is still empty,
+    /// the dimension is taken from this row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the row does not have `dimension()` values.
+    pub fn add<T: ToPrimitive>(&mut self, row: &[T]) {
+        if self.dimension == 0 && self.size == 0 {
+            *self = CovarianceMatrix::new(row.len());
+        }
+        assert_eq!(
+            row.len(),
+            self.dimension,
+            "row has a different number of values than the covariance matrix"
+        );
+        self.size += 1;
+        let n = self.size as f64;
+        let f = (n - 1.0) / n;
+        let values: Vec<f64> = row.iter().map(|x| x.to_f64().unwrap()).collect();
+        // The co-moments use the deltas from the means before this row.
+        let deltas: Vec<f64> = values.iter().zip(&self.means).map(|(x, m)| x - m).collect();
+        let mut k = 0;
+        for (i, &di) in deltas.iter().enumerate() {
+            let di = di * f;
+            for &dj in &deltas[i..] {
+                self.comoments[k] = di.mul_add(dj, self.comoments[k]);
+                k += 1;
+            }
+        }
+        for ((x, mean), column) in values.iter().zip(&mut self.means).zip(&mut self.columns) {
+            *mean += (x - *mean) / n;
+            column.add(x);
+        }
+    }
+
+    /// Returns the number of values in each row.
+    #[inline]
+    #[must_use]
+    pub const fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Returns the current statistics of each column.
+    #[inline]
+    #[must_use]
+    pub fn columns(&self) -> &[OnlineStats] {
+        &self.columns
+    }
+
+    /// Return the current population covariance (divided by `n`) of columns
+    /// `i` and `j`.
+    #[must_use]
+    pub fn population_covariance(&self, i: usize, j: usize) -> f64 {
+        self.comoment(i, j) / (self.size as f64)
+    }
+
+    /// Return the current sample covariance (divided by `n - 1`) of columns
+    /// `i` and `j`.
+    ///
+    /// This is `NaN` when there are fewer than two rows.
+    #[must_use]
+    pub fn sample_covariance(&self, i: usize, j: usize) -> f64 {
+        if self.size < 2 {
+            return f64::NAN;
+        }
+        self.comoment(i, j) / ((self.size - 1) as f64)
+    }
+
+    /// Return the current Pearson correlation coefficient of columns `i` and
+    /// `j`.
+    ///
+    /// This is `NaN` if either column is constant.
+    #[must_use]
+    pub fn correlation(&self, i: usize, j: usize) -> f64 {
+        let r = self.comoment(i, j) / (self.comoment(i, i) * self.comoment(j, j)).sqrt();
+        r.clamp(-1.0, 1.0)
+    }
+
+    /// Return the current population covariance matrix.
+    #[must_use]
+    pub fn population_covariance_matrix(&self) -> Vec<Vec<f64>> {
+        self.matrix(|i, j| self.population_covariance(i, j))
+    }
+
+    /// Return the current sample covariance matrix.
+    #[must_use]
+    pub fn sample_covariance_matrix(&self) -> Vec<Vec<f64>> {
+        self.matrix(|i, j| self.sample_covariance(i, j))
+    }
+
+    /// Return the current matrix of Pearson correlation coefficients.
+    #[must_use]
+    pub fn correlation_matrix(&self) -> Vec<Vec<f64>> {
+        self.matrix(|i, j| self.correlation(i, j))
+    }
+
+    /// Returns the number of rows.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns if empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn matrix<F: Fn(usize, usize) -> f64>(&self, f: F) -> Vec<Vec<f64>> {
+        (0..self.dimension)
+            .map(|i| (0..self.dimension).map(|j| f(i, j)).collect())
+            .collect()
+    }
+
+    /// Returns the co-moment of columns `i` and `j`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` or `j` is not less than `dimension()`.
+    fn comoment(&self, i: usize, j: usize) -> f64 {
+        assert!(
+            i < self.dimension && j < self.dimension,
+            "column index out of bounds"
+        );
+        let (i, j) = if i <= j { (i, j) } else { (j, i) };
+        // Rows before `i` hold `dimension - r` entries each.
+        let row_start = i * (2 * self.dimension - i + 1) / 2;
+        self.comoments[row_start + j - i]
+    }
+}
+
+impl Commute for CovarianceMatrix {
+    #[inline]
+    fn merge(&mut self, v: CovarianceMatrix) {
+        if v.size == 0 {
+            return;
+        }
+        if self.size == 0 {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.dimension, v.dimension,
+            "cannot merge covariance matrices with different dimensions"
+        );
+
+        // The same pairwise formula as `OnlineCovariance::merge`, for every
+        // pair of columns.
+        let (s1, s2) = (self.size as f64, v.size as f64);
+        let n = s1 + s2;
+        let f = s1 * s2 / n;
+        let mut k = 0;
+        for i in 0..self.dimension {
+            let di = v.means[i] - self.means[i];
+            for j in i..self.dimension {
+                let dj = v.means[j] - self.means[j];
+                self.comoments[k] += (di * dj).mul_add(f, v.comoments[k]);
+                k += 1;
+            }
+        }
+        for (m1, m2) in self.means.iter_mut().zip(v.means) {
+            *m1 = s1.mul_add(*m1, s2 * m2) / n;
+        }
+        for (c1, c2) in self.columns.iter_mut().zip(v.columns) {
+            c1.merge(c2);
+        }
+        self.size += v.size;
+    }
+}
+
+impl Default for CovarianceMatrix {
+    fn default() -> CovarianceMatrix {
+        CovarianceMatrix::new(0)
+    }
+}
+
+impl fmt::Debug for CovarianceMatrix {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.correlation_matrix())
+    }
+}
+
+impl<R: AsRef<[f64]>> FromIterator<R> for CovarianceMatrix {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = R>>(it: I) -> CovarianceMatrix {
+        let mut v = CovarianceMatrix::default();
+        v.extend(it);
+        v
+    }
+}
+
+impl<R: AsRef<[f64]>> Extend<R> for CovarianceMatrix {
+    #[inline]
+    fn extend<I: IntoIterator<Item = R>>(&mut self, it: I) {
+        for row in it {
+            self.add(row.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CovarianceMatrix, OnlineCovariance};
+    use crate::{merge_all, Commute};
+
+    fn assert_close(got: f64, expected: f64) {
+        assert!((got - expected).abs() < 1e-12, "{got} != {expected}");
+    }
+
+    #[test]
+    fn covariance() {
+        let cov: OnlineCovariance = [(1, 2), (2, 4), (3, 5), (4, 4), (5, 5)]
+            .into_iter()
+            .collect();
+        assert_eq!(cov.len(), 5);
+        assert_close(cov.mean_x(), 3.0);
+        assert_close(cov.mean_y(), 4.0);
+        assert_close(cov.population_covariance(), 1.2);
+        assert_close(cov.sample_covariance(), 1.5);
+        assert_close(cov.pearson(), 0.774_596_669_241_483_4);
+        assert_close(cov.slope(), 0.6);
+        assert_close(cov.intercept(), 2.2);
+        assert_close(cov.r_squared(), 0.6);
+    }
+
+    #[test]
+    fn covariance_perfect() {
+        let cov: OnlineCovariance = (0..100).map(|x| (x, 1e9 - 3.0 * f64::from(x))).collect();
+        assert_close(cov.pearson(), -1.0);
+        assert_close(cov.slope(), -3.0);
+        assert!((cov.intercept() - 1e9).abs() < 1e-5);
+    }
+
+    #[test]
+    fn covariance_degenerate() {
+        let cov = OnlineCovariance::new();
+        assert!(cov.is_empty());
+        assert!(cov.sample_covariance().is_nan());
+
+        let cov: OnlineCovariance = [(1.0, 2.0), (1.0, 3.0)].into_iter().collect();
+        assert!(cov.pearson().is_nan());
+        assert!(cov.slope().is_nan());
+    }
+
+    #[test]
+    fn covariance_merge() {
+        let pairs: Vec<(f64, f64)> = (0..1_000)
+            .map(|i| {
+                let x = f64::from(i);
+                (x, (x * 0.37).sin() * 10.0 + x * 0.5)
+            })
+            .collect();
+        let expected: OnlineCovariance = pairs.iter().copied().collect();
+        let got = merge_all(
+            pairs
+                .chunks(77)
+                .map(|c| c.iter().copied().collect::<OnlineCovariance>()),
+        )
+        .unwrap();
+        assert_eq!(got.len(), expected.len());
+        for (a, b) in [
+            (got.mean_x(), expected.mean_x()),
+            (got.mean_y(), expected.mean_y()),
+            (got.sample_covariance(), expected.sample_covariance()),
+            (got.pearson(), expected.pearson()),
+            (got.slope(), expected.slope()),
+        ] {
+            assert!((a - b).abs() <= 1e-9 * b.abs().max(1.0), "{a} != {b}");
+        }
+
+        let mut one = expected;
+        one.merge(OnlineCovariance::new());
+        assert_eq!(one, expected);
+    }
+
+    fn rows() -> Vec<[f64; 3]> {
+        (0..500)
+            .map(|i| {
+                let x = f64::from(i);
+                [x, (x * 0.37).sin() * 10.0 + x * 0.5, 1e6 - (x * 0.11).cos()]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn covariance_matrix() {
+        let rows = rows();
+        let matrix: CovarianceMatrix = rows.iter().collect();
+        assert_eq!(matrix.dimension(), 3);
+        assert_eq!(matrix.len(), 500);
+
+        let correlations = matrix.correlation_matrix();
+        let covariances = matrix.sample_covariance_matrix();
+        for i in 0..3 {
+            for j in 0..3 {
+                let pair: OnlineCovariance = rows.iter().map(|r| (r[i], r[j])).collect();
+                let (r, cov) = (pair.pearson(), pair.sample_covariance());
+                assert!((correlations[i][j] - r).abs() < 1e-9, "{i} {j}");
+                assert!((covariances[i][j] - cov).abs() < 1e-9 * cov.abs().max(1.0));
+                assert_eq!(correlations[i][j], correlations[j][i]);
+            }
+            assert!((correlations[i][i] - 1.0).abs() < 1e-12);
+            let column: crate::OnlineStats = rows.iter().map(|r| r[i]).collect();
+            assert_eq!(matrix.columns()[i], column);
+            assert!(
+                (matrix.population_covariance(i, i) - column.population_variance()).abs()
+                    < 1e-9 * column.population_variance()
+            );
+        }
+    }
+
+    #[test]
+    fn covariance_matrix_merge() {
+        let rows = rows();
+        let expected: CovarianceMatrix = rows.iter().collect();
+        let got = merge_all(
+            rows.chunks(33)
+                .map(|c| c.iter().collect::<CovarianceMatrix>()),
+        )
+        .unwrap();
+        assert_eq!(got.len(), expected.len());
+        let (a, b) = (
+            got.sample_covariance_matrix(),
+            expected.sample_covariance_matrix(),
+        );
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((a[i][j] - b[i][j]).abs() <= 1e-7 * b[i][j].abs().max(1.0));
+            }
+        }
+
+        let mut one = expected.clone();
+        one.merge(CovarianceMatrix::default());
+        assert_eq!(one, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "different number of values")]
+    fn covariance_matrix_row_mismatch() {
+        let mut matrix = CovarianceMatrix::new(3);
+        matrix.add(&[1, 2]);
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+/// A data structure for the exponentially weighted moving mean and variance
+/// of a stream.
+///
+/// The `i`-th most recent sample (from `0`) has weight `(1 - alpha)^i`, and
+/// the statistics are normalized by the sum of the weights. This matches
+/// `ewm(adjust=True)` in pandas, so early samples are not biased towards
+/// zero, and `variance` is corrected for the effective number of samples
+/// like pandas' `var(bias=False)`.
+///
+/// `NaN`s are skipped, but still age the samples before them, as with
+/// `ignore_na=False` in pandas.
+///
+/// Only a handful of sums are kept, so this uses constant space. Since the
+/// weights depend on the order of the samples, this does not implement
+/// `Commute`.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ewm {
+    alpha: f64,
+    size: u64,
+    /// The sum of the weights.
+    weight: f64,
+    /// The sum of the squared weights.
+    weight2: f64,
+    mean: f64,
+    /// The weighted sum of squared deviations from the mean.
+    q: f64,
+}
+
+impl Ewm {
+    /// Create an empty state with smoothing factor `alpha`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `alpha` is not in `(0, 1]`.
+    #[must_use]
+    pub fn with_alpha(alpha: f64) -> Ewm {
+        assert!(alpha > 0.0 && alpha <= 1.0, "Ewm alpha must be in (0, 1]");
+        Ewm {
+            alpha,
+            size: 0,
+            weight: 0.0,
+            weight2: 0.0,
+            mean: 0.0,
+            q: 0.0,
+        }
+    }
+
+    /// Create an empty state where a sample's weight halves every
+    /// `halflife` samples, i.e. `alpha = 1 - exp(-ln(2) / halflife)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `halflife` is not positive.
+    #[must_use]
+    pub fn with_halflife(halflife: f64) -> Ewm {
+        assert!(halflife > 0.0, "Ewm half-life must be positive");
+        Ewm::with_alpha(1.0 - (-std::f64::consts::LN_2 / halflife).exp())
+    }
+
+    /// Create an empty state with `alpha = 2 / (span + 1)`, which gives
+    /// about the same average age as a `span`-sample moving average.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `span` is less than `1`.
+    #[must_use]
+    pub fn with_span(span: f64) -> Ewm {
+        assert!(span >= 1.0, "Ewm span must be at least 1");
+        Ewm::with_alpha(2.0 / (span + 1.0))
+    }
+
+    /// Create an empty state with `alpha = 1 / (1 + com)`, where `com` is
+    /// the center of mass of the weights.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `com` is negative.
+    #[must_use]
+    pub fn with_com(com: f64) -> Ewm {
+        assert!(com >= 0.0, "Ewm center of mass must be non-negative");
+        Ewm::with_alpha(1.0 / (1.0 + com))
+    }
+
+    /// Add a new sample.
+    #[inline]
+    pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        let decay = 1.0 - self.alpha;
+        self.weight *= decay;
+        self.weight2 *= decay * decay;
+        self.q *= decay;
+
+        let x = sample.to_f64().unwrap_or(f64::NAN);
+        if x.is_nan() {
+            return;
+        }
+        self.size += 1;
+        self.weight += 1.0;
+        self.weight2 += 1.0;
+        let delta = x - self.mean;
+        self.mean += delta / self.weight;
+        self.q = delta.mul_add(x - self.mean, self.q);
+    }
+
+    /// Return the smoothing factor.
+    #[inline]
+    #[must_use]
+    pub const fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Return the exponentially weighted mean.
+    ///
+    /// If there are no samples, `NaN` is returned.
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            self.mean
+        }
+    }
+
+    /// Return the bias-corrected exponentially weighted variance.
+    ///
+    /// If there are fewer than two samples, `NaN` is returned.
+    #[must_use]
+    pub fn variance(&self) -> f64 {
+        let w2 = self.weight * self.weight;
+        let effective = w2 - self.weight2;
+        if self.size < 2 || effective <= 0.0 {
+            return f64::NAN;
+        }
+        (self.q / self.weight * w2 / effective).max(0.0)
+    }
+
+    /// Return the exponentially weighted variance without the bias
+    /// correction, i.e. the weighted mean of the squared deviations.
+    ///
+    /// If there are no samples, `NaN` is returned.
+    #[must_use]
+    pub fn population_variance(&self) -> f64 {
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            (self.q / self.weight).max(0.0)
+        }
+    }
+
+    /// Return the bias-corrected exponentially weighted standard deviation.
+    #[must_use]
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Return the number of samples, excluding `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns true if there are no samples.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl fmt::Debug for Ewm {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.10} +/- {:.10}", self.mean(), self.stddev())
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for Ewm {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ewm;
+
+    /// The pandas `adjust=True` definitions, computed from scratch.
+    fn brute_force(data: &[f64], alpha: f64) -> (f64, f64) {
+        let n = data.len();
+        let weights: Vec<f64> = (0..n)
+            .map(|i| (1.0 - alpha).powi((n - 1 - i) as i32))
+            .zip(data)
+            .map(|(w, x)| if x.is_nan() { 0.0 } else { w })
+            .collect();
+        let sum_w: f64 = weights.iter().sum();
+        let sum_w2: f64 = weights.iter().map(|w| w * w).sum();
+        let mean = weights
+            .iter()
+            .zip(data)
+            .filter(|(&w, _)| w > 0.0)
+            .map(|(w, x)| w * x)
+            .sum::<f64>()
+            / sum_w;
+        let q: f64 = weights
+            .iter()
+            .zip(data)
+            .filter(|(&w, _)| w > 0.0)
+            .map(|(w, x)| w * (x - mean) * (x - mean))
+            .sum();
+        (mean, q / sum_w * sum_w * sum_w / (sum_w * sum_w - sum_w2))
+    }
+
+    #[test]
+    fn ewm_pandas() {
+        let mut ewm = Ewm::with_alpha(0.5);
+        let expected = [1.0, 5.0 / 3.0, 17.0 / 7.0, 49.0 / 15.0];
+        for (x, mean) in [1.0, 2.0, 3.0, 4.0].iter().zip(expected) {
+            ewm.add(x);
+            assert!((ewm.mean() - mean).abs() < 1e-12);
+        }
+        let mut pair = Ewm::with_alpha(0.5);
+        pair.extend([1, 2]);
+        assert!((pair.variance() - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ewm_brute_force() {
+        let data: Vec<f64> = (0..200)
+            .map(|i| {
+                if i % 37 == 5 {
+                    f64::NAN
+                } else {
+                    ((i * 7_919) % 113) as f64 / 7.0 - 5.0
+                }
+            })
+            .collect();
+        for alpha in [0.05, 0.3, 0.9] {
+            let mut ewm = Ewm::with_alpha(alpha);
+            for i in 0..data.len() {
+                ewm.add(&data[i]);
+                let (mean, variance) = brute_force(&data[..=i], alpha);
+                assert!((ewm.mean() - mean).abs() < 1e-9, "{alpha} {i}");
+                if variance.is_finite() {
+                    assert!((ewm.variance() - variance).abs() < 1e-7, "{alpha} {i}");
+                } else {
+                    assert!(ewm.variance().is_nan());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ewm_parameters() {
+        assert!((Ewm::with_span(9.0).alpha() - 0.2).abs() < 1e-15);
+        assert!((Ewm::with_com(4.0).alpha() - 0.2).abs() < 1e-15);
+        assert!((Ewm::with_halflife(1.0).alpha() - 0.5).abs() < 1e-15);
+
+        // With alpha = 1, only the latest sample counts.
+        let mut last = Ewm::with_alpha(1.0);
+        last.extend([3.0, 8.0]);
+        assert_eq!(last.mean(), 8.0);
+        assert_eq!(last.population_variance(), 0.0);
+        assert!(last.variance().is_nan());
+
+        let empty = Ewm::with_span(10.0);
+        assert!(empty.is_empty());
+        assert!(empty.mean().is_nan());
+        assert!(empty.variance().is_nan());
+    }
+
+    #[test]
+    fn ewm_serde() {
+        let mut ewm = Ewm::with_halflife(3.0);
+        ewm.extend([1.0, 4.0, 2.0]);
+        let json = serde_json::to_string(&ewm).unwrap();
+        let back: Ewm = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, ewm);
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must be in")]
+    fn ewm_bad_alpha() {
+        let _ = Ewm::with_alpha(0.0);
+    }
+}
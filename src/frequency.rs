@@ -2,11 +2,17 @@ use ahash::AHashMap;
 use std::collections::hash_map::{Entry, Keys};
 use std::fmt;
 use std::hash::Hash;
+use std::marker::PhantomData;
 
 use rayon::prelude::*;
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
 
-use crate::Commute;
+use crate::{Commute, Subtract, PAR_CHUNK_SIZE};
 /// A commutative data structure for exact frequency counts.
+///
+/// This serializes as a sequence of `(value, count)` pairs in arbitrary
+/// order. Use `sorted` for a deterministic encoding.
 #[derive(Clone)]
 pub struct Frequencies<T> {
     data: AHashMap<T, u64>,
@@ -26,6 +32,31 @@ impl<T: Eq + Hash> Frequencies<T> {
         Default::default()
     }
 
+    /// Create a frequency table of `samples`, splitting the work across the
+    /// rayon thread pool.
+    #[must_use]
+    pub fn par_from_slice(samples: &[T]) -> Frequencies<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(|chunk| chunk.iter().cloned().collect())
+            .reduce(Frequencies::empty, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+
+    /// An empty table that, unlike `Default`, does not preallocate, for the
+    /// identities of parallel folds, where every split creates one.
+    #[inline]
+    fn empty() -> Frequencies<T> {
+        Frequencies {
+            data: AHashMap::new(),
+        }
+    }
+
     /// Add a sample to the frequency table.
     #[inline]
     pub fn add(&mut self, v: T) {
@@ -39,6 +70,44 @@ impl<T: Eq + Hash> Frequencies<T> {
         }
     }
 
+    /// Add `count` occurrences of a sample to the frequency table.
+    ///
+    /// This is the same as calling `add` `count` times, e.g. for data that
+    /// has already been aggregated into value/count pairs.
+    #[inline]
+    pub fn add_n(&mut self, v: T, count: u64) {
+        if count == 0 {
+            return;
+        }
+        *self.data.entry(v).or_insert(0) += count;
+    }
+
+    /// Remove one occurrence of a sample from the frequency table.
+    ///
+    /// Values whose count drops to `0` are removed entirely. Returns false
+    /// if `v` was not in the table.
+    #[inline]
+    pub fn remove(&mut self, v: &T) -> bool {
+        self.remove_n(v, 1)
+    }
+
+    /// Remove up to `count` occurrences of a sample from the frequency
+    /// table.
+    ///
+    /// Values whose count drops to `0` are removed entirely. Returns false
+    /// if `v` was not in the table.
+    #[inline]
+    pub fn remove_n(&mut self, v: &T, count: u64) -> bool {
+        let Some(current) = self.data.get_mut(v) else {
+            return false;
+        };
+        *current = current.saturating_sub(count);
+        if *current == 0 {
+            self.data.remove(v);
+        }
+        true
+    }
+
     /// Return the number of occurrences of `v` in the data.
     #[inline]
     #[must_use]
@@ -83,7 +152,7 @@ impl<T: Eq + Hash> Frequencies<T> {
                 (k, v)
             })
             .collect();
-        counts.sort_unstable_by(|&(_, c1), &(_, c2)| c2.cmp(&c1));
+        counts.sort_unstable_by_key(|&(_, c)| std::cmp::Reverse(c));
         (counts, total_count)
     }
 
@@ -101,10 +170,44 @@ impl<T: Eq + Hash> Frequencies<T> {
                 (k, v)
             })
             .collect();
-        counts.sort_unstable_by(|&(_, c1), &(_, c2)| c1.cmp(&c2));
+        counts.sort_unstable_by_key(|&(_, c)| c);
         (counts, total_count)
     }
 
+    /// Return the `k` most frequent elements and their counts, in
+    /// descending order of count.
+    ///
+    /// Unlike `most_frequent`, only the `k` returned elements are sorted, so
+    /// this takes `O(n + k log k)` time. The order of elements with equal
+    /// counts is unspecified.
+    #[must_use]
+    pub fn top_k(&self, k: usize) -> Vec<(&T, u64)> {
+        let mut counts: Vec<_> = self.data.iter().map(|(k, &v)| (k, v)).collect();
+        if k < counts.len() {
+            counts.select_nth_unstable_by_key(k, |&(_, c)| std::cmp::Reverse(c));
+            counts.truncate(k);
+        }
+        counts.sort_unstable_by_key(|&(_, c)| std::cmp::Reverse(c));
+        counts
+    }
+
+    /// Return the `k` least frequent elements and their counts, in
+    /// ascending order of count.
+    ///
+    /// Unlike `least_frequent`, only the `k` returned elements are sorted, so
+    /// this takes `O(n + k log k)` time. The order of elements with equal
+    /// counts is unspecified.
+    #[must_use]
+    pub fn bottom_k(&self, k: usize) -> Vec<(&T, u64)> {
+        let mut counts: Vec<_> = self.data.iter().map(|(k, &v)| (k, v)).collect();
+        if k < counts.len() {
+            counts.select_nth_unstable_by_key(k, |&(_, c)| c);
+            counts.truncate(k);
+        }
+        counts.sort_unstable_by_key(|&(_, c)| c);
+        counts
+    }
+
     /// Return a `Vec` of elements, their corresponding counts in order
     /// based on the `least` parameter, and the total count. Uses parallel sort.
     #[inline]
@@ -158,11 +261,24 @@ impl<T: Eq + Hash> Frequencies<T> {
 
     /// Return an iterator over the unique values of the data.
     #[must_use]
-    pub fn unique_values(&self) -> UniqueValues<T> {
+    pub fn unique_values(&self) -> UniqueValues<'_, T> {
         UniqueValues {
             data_keys: self.data.keys(),
         }
     }
+
+    /// Return a view of the frequency table that serializes its
+    /// `(value, count)` pairs sorted by value.
+    ///
+    /// This makes snapshots deterministic, so they diff cleanly. The result
+    /// deserializes back into a `Frequencies` like the default encoding.
+    #[must_use]
+    pub fn sorted(&self) -> SortedFrequencies<'_, T>
+    where
+        T: Ord,
+    {
+        SortedFrequencies { freqs: self }
+    }
 }
 
 impl<T: Eq + Hash> Commute for Frequencies<T> {
@@ -181,6 +297,15 @@ impl<T: Eq + Hash> Commute for Frequencies<T> {
     }
 }
 
+impl<T: Eq + Hash> Subtract for Frequencies<T> {
+    #[inline]
+    fn subtract(&mut self, v: &Frequencies<T>) {
+        for (k, &count) in &v.data {
+            self.remove_n(k, count);
+        }
+    }
+}
+
 impl<T: Eq + Hash> Default for Frequencies<T> {
     #[inline]
     fn default() -> Frequencies<T> {
@@ -199,6 +324,21 @@ impl<T: Eq + Hash> FromIterator<T> for Frequencies<T> {
     }
 }
 
+impl<T: Eq + Hash + Send> FromParallelIterator<T> for Frequencies<T> {
+    #[inline]
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(it: I) -> Frequencies<T> {
+        it.into_par_iter()
+            .fold(Frequencies::empty, |mut v, sample| {
+                v.add(sample);
+                v
+            })
+            .reduce(Frequencies::empty, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+}
+
 impl<T: Eq + Hash> Extend<T> for Frequencies<T> {
     #[inline]
     fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
@@ -208,6 +348,57 @@ impl<T: Eq + Hash> Extend<T> for Frequencies<T> {
     }
 }
 
+impl<T: Eq + Hash + Serialize> Serialize for Frequencies<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.data.iter())
+    }
+}
+
+impl<'de, T: Eq + Hash + Deserialize<'de>> Deserialize<'de> for Frequencies<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FrequenciesVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Eq + Hash + Deserialize<'de>> Visitor<'de> for FrequenciesVisitor<T> {
+            type Value = Frequencies<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence of (value, count) pairs")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut freqs = Frequencies {
+                    data: AHashMap::with_capacity(seq.size_hint().unwrap_or(0)),
+                };
+                // Duplicate values are summed, as if the tables were merged.
+                while let Some((value, count)) = seq.next_element::<(T, u64)>()? {
+                    if count > 0 {
+                        *freqs.data.entry(value).or_insert(0) += count;
+                    }
+                }
+                Ok(freqs)
+            }
+        }
+
+        deserializer.deserialize_seq(FrequenciesVisitor(PhantomData))
+    }
+}
+
+/// A view of a frequency table that serializes its `(value, count)` pairs
+/// sorted by value.
+///
+/// This is created by `Frequencies::sorted`.
+pub struct SortedFrequencies<'a, T> {
+    freqs: &'a Frequencies<T>,
+}
+
+impl<T: Ord + Serialize> Serialize for SortedFrequencies<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut pairs: Vec<_> = self.freqs.data.iter().collect();
+        pairs.sort_unstable_by_key(|&(v, _)| v);
+        serializer.collect_seq(pairs)
+    }
+}
+
 /// An iterator over unique values in a frequencies count.
 pub struct UniqueValues<'a, K> {
     data_keys: Keys<'a, K, u64>,
@@ -223,12 +414,14 @@ impl<'a, K> Iterator for UniqueValues<'a, K> {
 #[cfg(test)]
 mod test {
     use super::Frequencies;
+    use crate::{Commute, Subtract};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
     use std::iter::FromIterator;
 
     #[test]
     fn ranked() {
         let mut counts = Frequencies::new();
-        counts.extend(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4].into_iter());
+        counts.extend(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4]);
         let (most_count, most_total) = counts.most_frequent();
         assert_eq!(most_count[0], (&2, 5));
         assert_eq!(most_total, 11);
@@ -241,7 +434,7 @@ mod test {
     #[test]
     fn ranked2() {
         let mut counts = Frequencies::new();
-        counts.extend(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4].into_iter());
+        counts.extend(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4]);
         let (most_count, most_total) = counts.par_frequent(false);
         assert_eq!(most_count[0], (&2, 5));
         assert_eq!(most_total, 11);
@@ -250,6 +443,66 @@ mod test {
         assert_eq!(least_total, 11);
     }
 
+    #[test]
+    fn add_n() {
+        let mut counts = Frequencies::new();
+        counts.add_n("a", 3);
+        counts.add("a");
+        counts.add_n("b", 2);
+        counts.add_n("c", 0);
+        assert_eq!(counts.count(&"a"), 4);
+        assert_eq!(counts.cardinality(), 2);
+        assert_eq!(counts.most_frequent(), (vec![(&"a", 4), (&"b", 2)], 6));
+    }
+
+    #[test]
+    fn top_bottom_k() {
+        let counts = Frequencies::from_iter(vec![1usize, 1, 2, 2, 2, 2, 2, 3, 4, 4, 4]);
+        assert_eq!(counts.top_k(2), vec![(&2, 5), (&4, 3)]);
+        assert_eq!(counts.bottom_k(2), vec![(&3, 1), (&1, 2)]);
+        assert_eq!(counts.top_k(10).len(), 4);
+        assert_eq!(counts.top_k(0), vec![]);
+        assert_eq!(counts.top_k(4), counts.most_frequent().0);
+    }
+
+    #[test]
+    fn serde_json_roundtrip() {
+        let freqs = Frequencies::from_iter(vec!["b", "a", "c", "a", "b", "a"]);
+        let json = serde_json::to_string(&freqs.sorted()).unwrap();
+        assert_eq!(json, r#"[["a",3],["b",2],["c",1]]"#);
+
+        let unsorted_json = serde_json::to_string(&freqs).unwrap();
+        let back: Frequencies<String> = serde_json::from_str(&unsorted_json).unwrap();
+        assert_eq!(back.len(), 3);
+        assert_eq!(back.count(&"a".to_string()), 3);
+        assert_eq!(back.count(&"c".to_string()), 1);
+
+        let summed: Frequencies<u8> = serde_json::from_str("[[1,2],[2,0],[1,3]]").unwrap();
+        assert_eq!(summed.count(&1), 5);
+        assert_eq!(summed.len(), 1);
+    }
+
+    #[test]
+    fn serde_bincode_roundtrip() {
+        let freqs = Frequencies::from_iter(vec![8u64, 6, 5, 1, 1, 2, 2, 2, 3, 4, 7, 4, 4]);
+        let bytes = bincode::serialize(&freqs).unwrap();
+        let back: Frequencies<u64> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(back.sorted_pairs(), freqs.sorted_pairs());
+
+        let sorted_bytes = bincode::serialize(&freqs.sorted()).unwrap();
+        let back: Frequencies<u64> = bincode::deserialize(&sorted_bytes).unwrap();
+        assert_eq!(back.sorted_pairs(), freqs.sorted_pairs());
+        assert_eq!(sorted_bytes, bincode::serialize(&back.sorted()).unwrap());
+    }
+
+    impl Frequencies<u64> {
+        fn sorted_pairs(&self) -> Vec<(u64, u64)> {
+            let mut pairs: Vec<_> = self.data.iter().map(|(&k, &v)| (k, v)).collect();
+            pairs.sort_unstable();
+            pairs
+        }
+    }
+
     #[test]
     fn unique_values() {
         let freqs = Frequencies::from_iter(vec![8, 6, 5, 1, 1, 2, 2, 2, 3, 4, 7, 4, 4]);
@@ -257,4 +510,40 @@ mod test {
         unique.sort_unstable();
         assert_eq!(unique, vec![1, 2, 3, 4, 5, 6, 7, 8]);
     }
+
+    #[test]
+    fn parallel() {
+        let data: Vec<u32> = (0..10_000u32).map(|i| i % 17).collect();
+        let sequential: Frequencies<u32> = data.iter().copied().collect();
+        let from_slice = Frequencies::par_from_slice(&data);
+        let parallel: Frequencies<u32> = data.into_par_iter().collect();
+        assert_eq!(parallel.len(), 17);
+        for v in 0..17 {
+            assert_eq!(parallel.count(&v), sequential.count(&v));
+            assert_eq!(from_slice.count(&v), sequential.count(&v));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let mut freqs: Frequencies<&str> = vec!["a", "b", "a", "c"].into_iter().collect();
+        assert!(freqs.remove(&"a"));
+        assert!(freqs.remove(&"b"));
+        assert!(!freqs.remove(&"b"));
+        assert!(!freqs.remove(&"z"));
+        assert_eq!(freqs.count(&"a"), 1);
+        assert_eq!(freqs.cardinality(), 2);
+
+        let before = freqs.clone();
+        let more: Frequencies<&str> = vec!["a", "d", "d"].into_iter().collect();
+        freqs.merge(more.clone());
+        assert_eq!(freqs.count(&"d"), 2);
+        freqs.subtract(&more);
+        assert_eq!(freqs.cardinality(), before.cardinality());
+        assert_eq!(freqs.count(&"a"), 1);
+        assert_eq!(freqs.count(&"d"), 0);
+
+        assert!(freqs.remove_n(&"c", 5));
+        assert_eq!(freqs.most_frequent().0, vec![(&"a", 1)]);
+    }
 }
@@ -0,0 +1,501 @@
+use std::fmt;
+
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::{Commute, OnlineStats, Subtract, Unsorted};
+
+/// A rule for choosing the number of bins of a `Histogram` from the data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BinRule {
+    /// `ceil(log2(n)) + 1` bins, which assumes roughly normal data and
+    /// undersmooths large data sets.
+    #[default]
+    Sturges,
+    /// Bins of width `(24 * sqrt(pi) / n)^(1/3) * stddev`, which minimizes
+    /// the integrated squared error for normal data.
+    Scott,
+    /// Bins of width `2 * IQR / n^(1/3)`, which is like `Scott` but robust
+    /// to outliers.
+    FreedmanDiaconis,
+    /// Sturges' rule with extra bins for skewed data, from Doane, D. P.
+    /// (1976), "Aesthetic frequency classifications".
+    Doane,
+}
+
+/// A commutative data structure for counting samples in bins with fixed
+/// edges.
+///
+/// Bin `i` holds the samples in `[edges[i], edges[i + 1])`, except for the
+/// last bin, which also holds samples equal to the last edge. Samples
+/// outside of the edges are counted separately, as are `NaN`s.
+///
+/// Histograms can only be merged if their edges are identical. The default
+/// histogram has no bins, and is only useful as the identity for `merge`.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    edges: Vec<f64>,
+    counts: Vec<u64>,
+    below: u64,
+    above: u64,
+    nans: u64,
+}
+
+impl Histogram {
+    /// Create an empty histogram with the given bin edges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are fewer than two edges, or they are not finite and
+    /// strictly increasing.
+    #[must_use]
+    pub fn with_edges(edges: Vec<f64>) -> Histogram {
+        assert!(
+            edges.len() >= 2
+                && edges.iter().all(|e| e.is_finite())
+                && edges.windows(2).all(|w| w[0] < w[1]),
+            "histogram edges must be finite and strictly increasing"
+        );
+        Histogram {
+            counts: vec![0; edges.len() - 1],
+            edges,
+            below: 0,
+            above: 0,
+            nans: 0,
+        }
+    }
+
+    /// Create an empty histogram with `bins` bins of equal width between
+    /// `min` and `max`.
+    ///
+    /// If `min` and `max` are too close together to be split into `bins`
+    /// distinct edges, the edges that would coincide are dropped, so there
+    /// are fewer bins.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bins` is `0`, or `min` and `max` are not finite with
+    /// `min < max`.
+    #[must_use]
+    pub fn with_bins(min: f64, max: f64, bins: usize) -> Histogram {
+        assert!(bins > 0, "histogram must have at least one bin");
+        let mut edges = vec![min];
+        for i in 1..bins {
+            // Interpolating between the ends never needs `max - min`, which
+            // overflows for ranges wider than `f64::MAX`.
+            let t = i as f64 / bins as f64;
+            let edge = min.mul_add(1.0 - t, max * t);
+            if edge > edges[edges.len() - 1] && edge < max {
+                edges.push(edge);
+            }
+        }
+        edges.push(max);
+        Histogram::with_edges(edges)
+    }
+
+    /// Create a histogram of the data, with bins of equal width spanning its
+    /// finite values, and as many bins as `rule` picks.
+    ///
+    /// The number of bins is at most the number of finite values, and may
+    /// be fewer when the values are too close together to split (see
+    /// `with_bins`). Rules that pick a bin width fall back to a single bin
+    /// when the width is `0` (e.g. the interquartile range is `0`).
+    ///
+    /// If there are no finite values, `None` is returned.
+    pub fn from_unsorted<T>(data: &mut Unsorted<T>, rule: BinRule) -> Option<Histogram>
+    where
+        T: PartialOrd + ToPrimitive,
+    {
+        let mut stats = OnlineStats::new();
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for x in data.values().filter_map(ToPrimitive::to_f64) {
+            if x.is_finite() {
+                stats.add(&x);
+                min = min.min(x);
+                max = max.max(x);
+            }
+        }
+        if stats.is_empty() {
+            return None;
+        }
+        if min == max {
+            // Give a single value a bin of its own, as numpy does, but widen
+            // it relative to its magnitude when `0.5` is below its precision.
+            let pad = (min.abs() * 1e-9).max(0.5);
+            min = (min - pad).max(f64::MIN);
+            max = (max + pad).min(f64::MAX);
+        }
+
+        let n = stats.len() as f64;
+        // Half of the range, which unlike the range cannot overflow.
+        let half_range = max / 2.0 - min / 2.0;
+        let bins_of_width = |width: f64| {
+            if width > 0.0 {
+                (half_range / width * 2.0).ceil()
+            } else {
+                1.0
+            }
+        };
+        let bins = match rule {
+            BinRule::Sturges => n.log2().ceil() + 1.0,
+            BinRule::Scott => {
+                let width = (24.0 * std::f64::consts::PI.sqrt() / n).cbrt() * stats.stddev();
+                bins_of_width(width)
+            }
+            BinRule::FreedmanDiaconis => {
+                let iqr = data.quartiles().map_or(0.0, |(q1, _, q3)| q3 - q1);
+                bins_of_width(2.0 * iqr / n.cbrt())
+            }
+            BinRule::Doane => {
+                let g1 = stats.skewness();
+                let sigma_g1 = (6.0 * (n - 2.0) / ((n + 1.0) * (n + 3.0))).sqrt();
+                let extra = if n > 2.0 && g1.is_finite() {
+                    (1.0 + g1.abs() / sigma_g1).log2()
+                } else {
+                    0.0
+                };
+                (1.0 + n.log2() + extra).ceil()
+            }
+        };
+        // An infinite count saturates, and is then capped like any other.
+        let bins = if bins >= 1.0 {
+            (bins as usize).min(stats.len())
+        } else {
+            1
+        };
+
+        let mut histogram = Histogram::with_bins(min, max, bins);
+        for x in data.values() {
+            histogram.add(x);
+        }
+        Some(histogram)
+    }
+
+    /// Add a sample to the histogram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the histogram has no bins.
+    #[inline]
+    pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        self.add_n(sample, 1);
+    }
+
+    /// Add `count` occurrences of a sample to the histogram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the histogram has no bins.
+    #[inline]
+    pub fn add_n<T: ToPrimitive>(&mut self, sample: &T, count: u64) {
+        let counter = self.counter(sample.to_f64().unwrap_or(f64::NAN));
+        *counter += count;
+    }
+
+    /// Returns the counter for `x`.
+    #[inline]
+    fn counter(&mut self, x: f64) -> &mut u64 {
+        assert!(!self.edges.is_empty(), "histogram has no bins");
+        if x.is_nan() {
+            return &mut self.nans;
+        }
+        let last = self.edges.len() - 1;
+        if x < self.edges[0] {
+            &mut self.below
+        } else if x > self.edges[last] {
+            &mut self.above
+        } else {
+            // The number of edges at or below `x`, less one, except that the
+            // last edge belongs to the last bin.
+            let bin = self.edges.partition_point(|&e| e <= x) - 1;
+            &mut self.counts[bin.min(last - 1)]
+        }
+    }
+
+    /// Returns the bin edges, which are one more than the bins.
+    #[inline]
+    #[must_use]
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    /// Returns the number of samples in each bin.
+    #[inline]
+    #[must_use]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Returns the probability density of each bin, i.e. its count divided
+    /// by its width and by the number of samples in all bins, so that the
+    /// densities integrate to `1`.
+    ///
+    /// If the bins are empty, every density is `NaN`.
+    #[must_use]
+    pub fn densities(&self) -> Vec<f64> {
+        let total = self.counts.iter().sum::<u64>() as f64;
+        self.counts
+            .iter()
+            .zip(self.edges.windows(2))
+            .map(|(&count, w)| count as f64 / (total * (w[1] - w[0])))
+            .collect()
+    }
+
+    /// Returns the number of samples below the first edge.
+    #[inline]
+    #[must_use]
+    pub const fn below(&self) -> u64 {
+        self.below
+    }
+
+    /// Returns the number of samples above the last edge.
+    #[inline]
+    #[must_use]
+    pub const fn above(&self) -> u64 {
+        self.above
+    }
+
+    /// Returns the number of `NaN`s added, which are not in any bin.
+    #[inline]
+    #[must_use]
+    pub const fn nan_count(&self) -> u64 {
+        self.nans
+    }
+
+    /// Returns the number of bins.
+    #[inline]
+    #[must_use]
+    pub fn bins(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns the number of samples, including those outside of the bins.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        (self.counts.iter().sum::<u64>() + self.below + self.above + self.nans) as usize
+    }
+
+    /// Returns true if there are no samples.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Commute for Histogram {
+    #[inline]
+    fn merge(&mut self, v: Histogram) {
+        if v.edges.is_empty() {
+            return;
+        }
+        if self.edges.is_empty() {
+            *self = v;
+            return;
+        }
+        assert!(
+            self.edges == v.edges,
+            "cannot merge histograms with different edges"
+        );
+        for (c1, c2) in self.counts.iter_mut().zip(v.counts) {
+            *c1 += c2;
+        }
+        self.below += v.below;
+        self.above += v.above;
+        self.nans += v.nans;
+    }
+}
+
+impl Subtract for Histogram {
+    #[inline]
+    fn subtract(&mut self, v: &Histogram) {
+        if v.edges.is_empty() {
+            return;
+        }
+        assert!(
+            self.edges == v.edges,
+            "cannot subtract histograms with different edges"
+        );
+        for (c1, c2) in self.counts.iter_mut().zip(&v.counts) {
+            *c1 = c1.saturating_sub(*c2);
+        }
+        self.below = self.below.saturating_sub(v.below);
+        self.above = self.above.saturating_sub(v.above);
+        self.nans = self.nans.saturating_sub(v.nans);
+    }
+}
+
+impl fmt::Debug for Histogram {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        for (count, w) in self.counts.iter().zip(self.edges.windows(2)) {
+            list.entry(&format_args!("[{}, {}): {count}", w[0], w[1]));
+        }
+        list.finish()
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for Histogram {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BinRule, Histogram};
+    use crate::{Commute, Subtract, Unsorted};
+
+    #[test]
+    fn fixed_edges() {
+        let mut h = Histogram::with_edges(vec![0.0, 1.0, 2.5, 4.0]);
+        h.extend([0.0, 0.5, 1.0, 2.4, 2.5, 4.0, -1.0, 4.5, f64::NAN]);
+        assert_eq!(h.counts(), &[2, 2, 2]);
+        assert_eq!((h.below(), h.above(), h.nan_count()), (1, 1, 1));
+        assert_eq!(h.len(), 9);
+        assert_eq!(h.bins(), 3);
+
+        let densities = h.densities();
+        assert_eq!(densities[0], 2.0 / 6.0);
+        assert_eq!(densities[1], 2.0 / 9.0);
+        let area: f64 = densities
+            .iter()
+            .zip(h.edges().windows(2))
+            .map(|(d, w)| d * (w[1] - w[0]))
+            .sum();
+        assert!((area - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn equal_bins() {
+        let mut h = Histogram::with_bins(0.0, 1.0, 4);
+        assert_eq!(h.edges(), &[0.0, 0.25, 0.5, 0.75, 1.0]);
+        h.extend((0..=100).map(|i| f64::from(i) / 100.0));
+        assert_eq!(h.counts(), &[25, 25, 25, 26]);
+    }
+
+    #[test]
+    fn merge() {
+        let edges = vec![0.0, 10.0, 20.0];
+        let mut a = Histogram::with_edges(edges.clone());
+        a.extend([1, 15, 30]);
+        let mut b = Histogram::with_edges(edges);
+        b.extend([2, 3, -4]);
+
+        let mut merged = Histogram::default();
+        merged.merge(a.clone());
+        merged.merge(b.clone());
+        merged.merge(Histogram::default());
+        assert_eq!(merged.counts(), &[3, 1]);
+        assert_eq!((merged.below(), merged.above()), (1, 1));
+
+        merged.subtract(&b);
+        assert_eq!(merged, a);
+    }
+
+    #[test]
+    #[should_panic(expected = "different edges")]
+    fn merge_mismatch() {
+        let mut a = Histogram::with_bins(0.0, 1.0, 2);
+        a.merge(Histogram::with_bins(0.0, 1.0, 3));
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly increasing")]
+    fn bad_edges() {
+        let _ = Histogram::with_edges(vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn rules() {
+        let data: Vec<f64> = (0..100).map(|i| f64::from((i * 37) % 100)).collect();
+        let mut unsorted: Unsorted<f64> = data.iter().copied().collect();
+        let bins = |unsorted: &mut Unsorted<f64>, rule| {
+            Histogram::from_unsorted(unsorted, rule).unwrap().bins()
+        };
+
+        // ceil(log2(100)) + 1
+        assert_eq!(bins(&mut unsorted, BinRule::Sturges), 8);
+        // stddev = 28.866, so the width is 21.709 over a range of 99.
+        assert_eq!(bins(&mut unsorted, BinRule::Scott), 5);
+        // IQR = 74.5 - 24.5, so the width is 21.544.
+        assert_eq!(bins(&mut unsorted, BinRule::FreedmanDiaconis), 5);
+        // Uniform data is not skewed, so this is 1 + log2(100) rounded up.
+        assert_eq!(bins(&mut unsorted, BinRule::Doane), 8);
+
+        let h = Histogram::from_unsorted(&mut unsorted, BinRule::Sturges).unwrap();
+        assert_eq!(h.edges().first(), Some(&0.0));
+        assert_eq!(h.edges().last(), Some(&99.0));
+        assert_eq!(h.counts().iter().sum::<u64>(), 100);
+
+        // Skewed data gets more bins from Doane than from Sturges.
+        let mut skewed: Unsorted<f64> = (1..=100).map(|i| f64::from(i).powi(3)).collect();
+        assert!(bins(&mut skewed, BinRule::Doane) > bins(&mut skewed, BinRule::Sturges));
+    }
+
+    #[test]
+    fn rules_degenerate() {
+        let mut constant: Unsorted<u32> = vec![5, 5, 5].into_iter().collect();
+        let h = Histogram::from_unsorted(&mut constant, BinRule::FreedmanDiaconis).unwrap();
+        assert_eq!(h.edges(), &[4.5, 5.5]);
+        assert_eq!(h.counts(), &[3]);
+
+        let mut nans: Unsorted<f64> = vec![f64::NAN].into_iter().collect();
+        assert!(Histogram::from_unsorted(&mut nans, BinRule::Scott).is_none());
+
+        let mut mixed: Unsorted<f64> = vec![1.0, 2.0, f64::INFINITY, f64::NAN]
+            .into_iter()
+            .collect();
+        let h = Histogram::from_unsorted(&mut mixed, BinRule::Sturges).unwrap();
+        assert_eq!(h.counts().iter().sum::<u64>(), 2);
+        assert_eq!((h.above(), h.nan_count()), (1, 1));
+    }
+
+    #[test]
+    fn large_single_value() {
+        let mut large: Unsorted<f64> = vec![1e20].into_iter().collect();
+        let h = Histogram::from_unsorted(&mut large, BinRule::Sturges).unwrap();
+        assert_eq!(h.bins(), 1);
+        assert!(h.edges()[0] < 1e20 && 1e20 < h.edges()[1]);
+        assert_eq!(h.counts(), &[1]);
+
+        let mut largest: Unsorted<f64> = vec![f64::MAX].into_iter().collect();
+        let h = Histogram::from_unsorted(&mut largest, BinRule::Scott).unwrap();
+        assert_eq!(h.counts(), &[1]);
+    }
+
+    #[test]
+    fn overflowing_range() {
+        let h = Histogram::with_bins(-1e308, 1e308, 4);
+        assert_eq!(h.edges(), &[-1e308, -5e307, 0.0, 5e307, 1e308]);
+
+        let mut wide: Unsorted<f64> = vec![-1e308, 1e308].into_iter().collect();
+        for rule in [
+            BinRule::Sturges,
+            BinRule::Scott,
+            BinRule::FreedmanDiaconis,
+            BinRule::Doane,
+        ] {
+            let h = Histogram::from_unsorted(&mut wide, rule).unwrap();
+            assert_eq!(h.counts().iter().sum::<u64>(), 2);
+        }
+    }
+
+    #[test]
+    fn sub_ulp_width() {
+        let h = Histogram::with_bins(1.0, 1.0 + f64::EPSILON, 10);
+        assert_eq!(h.edges(), &[1.0, 1.0 + f64::EPSILON]);
+
+        let mut close: Unsorted<f64> = (0..1000)
+            .map(|i| if i % 2 == 0 { 1.0 } else { 1.0 + f64::EPSILON })
+            .collect();
+        let h = Histogram::from_unsorted(&mut close, BinRule::FreedmanDiaconis).unwrap();
+        assert_eq!(h.bins(), 1);
+        assert_eq!(h.counts(), &[1000]);
+    }
+}
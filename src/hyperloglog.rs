@@ -0,0 +1,540 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use serde::de::{Deserializer, Error};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::{hash64, Commute};
+
+/// The default precision, which uses `2^12` registers (4 KB once dense) for
+/// a relative standard error of about 1.6%.
+const DEFAULT_PRECISION: u8 = 12;
+
+/// The precision of the sparse representation.
+const SPARSE_PRECISION: u8 = 25;
+
+/// Compute the approximate number of distinct values on a stream of data.
+///
+/// (This has time complexity `O(n)` and constant space complexity.)
+pub fn approx_cardinality<T, I>(it: I) -> u64
+where
+    T: Hash,
+    I: Iterator<Item = T>,
+{
+    it.collect::<HyperLogLog<T>>().cardinality()
+}
+
+/// A commutative data structure for approximate distinct counts.
+///
+/// This is HyperLogLog with the HLL++ improvements from Heule, S. et al.
+/// (2013), "HyperLogLog in Practice": 64-bit hashes, and a sparse
+/// representation at a higher precision while the number of distinct values
+/// is small, which is converted to the dense registers once it would use more
+/// memory than them.
+///
+/// Instead of HLL++'s empirical bias tables, dense estimates use the improved
+/// estimator from Ertl, O. (2017), "New cardinality estimation algorithms
+/// for HyperLogLog sketches", which corrects the same small- and large-range
+/// bias analytically.
+///
+/// Values are hashed with a fixed-key hasher, so sketches can be serialized
+/// and merged across threads, processes and files, as long as they share
+/// the same precision and were built with the same Rust toolchain and target.
+#[derive(Clone)]
+pub struct HyperLogLog<T> {
+    precision: u8,
+    /// Sorted by index, each entry encoded as `index << 6 | rank` at
+    /// `SPARSE_PRECISION`. Only used while `registers` is empty.
+    sparse: Vec<u32>,
+    /// Unsorted sparse entries not yet merged into `sparse`, which are
+    /// merged in batches so that adding a value takes amortized `O(log n)`
+    /// time rather than the `O(n)` of inserting into a sorted list.
+    buffer: Vec<u32>,
+    registers: Vec<u8>,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Hash> HyperLogLog<T> {
+    /// Create an empty sketch with the default precision (`12`).
+    #[must_use]
+    pub fn new() -> HyperLogLog<T> {
+        Default::default()
+    }
+
+    /// Create an empty sketch using `2^precision` registers.
+    ///
+    /// The relative standard error is about `1.04 / sqrt(2^precision)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `precision` is not in `4..=18`.
+    #[must_use]
+    pub fn with_precision(precision: u8) -> HyperLogLog<T> {
+        assert!(
+            (4..=18).contains(&precision),
+            "HyperLogLog precision must be between 4 and 18"
+        );
+        HyperLogLog {
+            precision,
+            ..Default::default()
+        }
+    }
+
+    /// Add a sample to the sketch.
+    #[inline]
+    pub fn add(&mut self, v: &T) {
+        let hash = hash64(v, 0);
+        if self.registers.is_empty() {
+            self.buffer.push(encode_sparse(hash));
+            if self.buffer.len() >= self.buffer_limit() {
+                self.flush();
+                if self.sparse.len() > self.sparse_limit() {
+                    self.densify();
+                }
+            }
+        } else {
+            let (index, rank) = dense_index_rank(hash, self.precision);
+            let register = &mut self.registers[index];
+            *register = (*register).max(rank);
+        }
+    }
+
+    /// Returns the estimated number of distinct values.
+    #[must_use]
+    pub fn cardinality(&self) -> u64 {
+        if self.registers.is_empty() {
+            // Linear counting is very accurate at the sparse precision.
+            let m = (1u64 << SPARSE_PRECISION) as f64;
+            let empty = m - self.merged_sparse().len() as f64;
+            return (m * (m / empty).ln()).round() as u64;
+        }
+        ertl_estimate(&self.registers, self.precision).round() as u64
+    }
+
+    /// Returns the precision, i.e. the base-2 log of the number of registers.
+    #[inline]
+    #[must_use]
+    pub const fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Returns the relative standard error of the estimates.
+    #[inline]
+    #[must_use]
+    pub fn relative_error(&self) -> f64 {
+        1.04 / f64::from(1u32 << self.precision).sqrt()
+    }
+
+    /// Returns true if no values have been added.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sparse.is_empty() && self.buffer.is_empty() && self.registers.iter().all(|&r| r == 0)
+    }
+
+    /// Merges the buffered entries into the sorted sparse list.
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            self.sparse = self.merged_sparse().into_owned();
+            self.buffer.clear();
+        }
+    }
+
+    fn densify(&mut self) {
+        let mut registers = vec![0u8; 1 << self.precision];
+        for entry in self.sparse.drain(..).chain(self.buffer.drain(..)) {
+            let (index, rank) = sparse_to_dense(entry, self.precision);
+            registers[index] = registers[index].max(rank);
+        }
+        self.registers = registers;
+    }
+}
+
+impl<T> HyperLogLog<T> {
+    /// The sparse representation uses 4 bytes per entry, so it is kept only
+    /// while it is smaller than the dense registers.
+    #[inline]
+    const fn sparse_limit(&self) -> usize {
+        (1 << self.precision) / 4
+    }
+
+    /// The buffer is flushed once it is a quarter of the largest sparse
+    /// list, which keeps the cost of the merges amortized `O(1)` per value.
+    #[inline]
+    const fn buffer_limit(&self) -> usize {
+        let limit = self.sparse_limit() / 4;
+        if limit == 0 {
+            1
+        } else {
+            limit
+        }
+    }
+
+    /// Returns the sparse list with the buffered entries merged in.
+    fn merged_sparse(&self) -> Cow<'_, [u32]> {
+        if self.buffer.is_empty() {
+            return Cow::Borrowed(&self.sparse);
+        }
+        let mut buffer = self.buffer.clone();
+        buffer.sort_unstable();
+        Cow::Owned(merge_sparse(&self.sparse, &buffer))
+    }
+}
+
+/// Merges two lists of sparse entries sorted by entry, keeping the highest
+/// rank of each index.
+fn merge_sparse(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut merged: Vec<u32> = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let entry = if j == b.len() || (i < a.len() && a[i] <= b[j]) {
+            i += 1;
+            a[i - 1]
+        } else {
+            j += 1;
+            b[j - 1]
+        };
+        // Entries for the same index are adjacent, in increasing rank.
+        match merged.last_mut() {
+            Some(last) if *last >> 6 == entry >> 6 => *last = entry,
+            _ => merged.push(entry),
+        }
+    }
+    merged
+}
+
+/// Splits a hash into its register index and rank (the position of the
+/// leftmost 1-bit after the index bits) at the given precision.
+#[inline]
+fn dense_index_rank(hash: u64, precision: u8) -> (usize, u8) {
+    let index = (hash >> (64 - precision)) as usize;
+    let rest = hash << precision;
+    let rank = rest.leading_zeros().min(u32::from(64 - precision)) + 1;
+    (index, rank as u8)
+}
+
+#[inline]
+fn encode_sparse(hash: u64) -> u32 {
+    let (index, rank) = dense_index_rank(hash, SPARSE_PRECISION);
+    (index as u32) << 6 | u32::from(rank)
+}
+
+/// Converts a sparse entry to a register index and rank at `precision`,
+/// exactly as if the original hash had been added at that precision.
+#[inline]
+fn sparse_to_dense(entry: u32, precision: u8) -> (usize, u8) {
+    let sparse_index = entry >> 6;
+    let sparse_rank = (entry & 0x3f) as u8;
+    let extra_bits = SPARSE_PRECISION - precision;
+    let index = (sparse_index >> extra_bits) as usize;
+    let extra = sparse_index & ((1 << extra_bits) - 1);
+    let rank = if extra == 0 {
+        extra_bits + sparse_rank
+    } else {
+        // Leading zeros within the `extra_bits` wide field, plus one.
+        (extra.leading_zeros() - (32 - u32::from(extra_bits)) + 1) as u8
+    };
+    (index, rank)
+}
+
+/// The improved raw estimator from Ertl (2017), section 4.
+fn ertl_estimate(registers: &[u8], precision: u8) -> f64 {
+    let m = registers.len() as f64;
+    let q = usize::from(64 - precision);
+    let mut histogram = vec![0u32; q + 2];
+    for &r in registers {
+        histogram[usize::from(r)] += 1;
+    }
+    let mut z = m * tau(1.0 - f64::from(histogram[q + 1]) / m);
+    for k in (1..=q).rev() {
+        z = 0.5 * (z + f64::from(histogram[k]));
+    }
+    z += m * sigma(f64::from(histogram[0]) / m);
+    m * m / (2.0 * std::f64::consts::LN_2 * z)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let z_prev = z;
+        z += x * y;
+        y += y;
+        if z == z_prev {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let z_prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == z_prev {
+            return z / 3.0;
+        }
+    }
+}
+
+impl<T: Hash> Commute for HyperLogLog<T> {
+    #[inline]
+    fn merge(&mut self, mut v: HyperLogLog<T>) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.precision, v.precision,
+            "cannot merge HyperLogLog sketches with different precisions"
+        );
+        self.flush();
+        v.flush();
+        if self.registers.is_empty() && v.registers.is_empty() {
+            self.sparse = merge_sparse(&self.sparse, &v.sparse);
+            if self.sparse.len() > self.sparse_limit() {
+                self.densify();
+            }
+            return;
+        }
+        if self.registers.is_empty() {
+            self.densify();
+        }
+        for entry in v.sparse {
+            let (index, rank) = sparse_to_dense(entry, self.precision);
+            self.registers[index] = self.registers[index].max(rank);
+        }
+        for (r1, r2) in self.registers.iter_mut().zip(v.registers) {
+            *r1 = (*r1).max(r2);
+        }
+    }
+}
+
+/// The serialized form of `HyperLogLog`, with the buffer merged into the
+/// sparse list, which is validated before use.
+#[derive(Serialize, Deserialize)]
+struct RawHyperLogLog<'a> {
+    precision: u8,
+    sparse: Cow<'a, [u32]>,
+    registers: Cow<'a, [u8]>,
+}
+
+impl<T> Serialize for HyperLogLog<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawHyperLogLog {
+            precision: self.precision,
+            sparse: self.merged_sparse(),
+            registers: Cow::Borrowed(&self.registers),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for HyperLogLog<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawHyperLogLog::deserialize(deserializer)?;
+        let precision = raw.precision;
+        if !(4..=18).contains(&precision) {
+            return Err(D::Error::custom(format!(
+                "HyperLogLog precision must be between 4 and 18, got {precision}"
+            )));
+        }
+        let hll = HyperLogLog {
+            precision,
+            sparse: raw.sparse.into_owned(),
+            buffer: Vec::new(),
+            registers: raw.registers.into_owned(),
+            _marker: PhantomData,
+        };
+        if hll.registers.is_empty() {
+            let max_rank = 64 - u32::from(SPARSE_PRECISION) + 1;
+            // A sketch densifies once its merged entries exceed the limit.
+            if hll.sparse.len() > hll.sparse_limit() + hll.buffer_limit()
+                || hll.sparse.iter().any(|e| {
+                    e >> 6 >= 1 << SPARSE_PRECISION || !(1..=max_rank).contains(&(e & 0x3f))
+                })
+                || hll.sparse.windows(2).any(|w| w[0] >> 6 >= w[1] >> 6)
+            {
+                return Err(D::Error::custom(
+                    "HyperLogLog sparse entries must be valid and sorted by index",
+                ));
+            }
+        } else {
+            let max_rank = 64 - precision + 1;
+            if !hll.sparse.is_empty()
+                || hll.registers.len() != 1 << precision
+                || hll.registers.iter().any(|&r| r > max_rank)
+            {
+                return Err(D::Error::custom(format!(
+                    "HyperLogLog must have 2^{precision} registers of rank at most {max_rank}"
+                )));
+            }
+        }
+        Ok(hll)
+    }
+}
+
+impl<T> PartialEq for HyperLogLog<T> {
+    fn eq(&self, other: &HyperLogLog<T>) -> bool {
+        self.precision == other.precision
+            && self.registers == other.registers
+            && self.merged_sparse() == other.merged_sparse()
+    }
+}
+
+impl<T> Eq for HyperLogLog<T> {}
+
+impl<T> Default for HyperLogLog<T> {
+    #[inline]
+    fn default() -> HyperLogLog<T> {
+        HyperLogLog {
+            precision: DEFAULT_PRECISION,
+            sparse: Vec::new(),
+            buffer: Vec::new(),
+            registers: Vec::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Hash> fmt::Debug for HyperLogLog<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "~{}", self.cardinality())
+    }
+}
+
+impl<T: Hash> FromIterator<T> for HyperLogLog<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> HyperLogLog<T> {
+        let mut v = HyperLogLog::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Hash> Extend<T> for HyperLogLog<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{approx_cardinality, HyperLogLog};
+    use crate::Commute;
+
+    fn assert_close(hll: &HyperLogLog<u64>, expected: u64) {
+        let got = hll.cardinality() as f64;
+        let error = (got - expected as f64).abs() / expected as f64;
+        assert!(
+            error < 4.0 * hll.relative_error(),
+            "estimated {got}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn hll_empty() {
+        let hll: HyperLogLog<u64> = HyperLogLog::new();
+        assert!(hll.is_empty());
+        assert_eq!(hll.cardinality(), 0);
+    }
+
+    #[test]
+    fn hll_sparse() {
+        let hll: HyperLogLog<u64> = (0..500).chain(0..500).collect();
+        assert!(hll.registers.is_empty());
+        assert_eq!(hll.cardinality(), 500);
+        assert_eq!(approx_cardinality(["a", "b", "a", "c"].into_iter()), 3);
+    }
+
+    #[test]
+    fn hll_dense() {
+        for precision in [4, 10, 14] {
+            for n in [100, 10_000, 1_000_000] {
+                let mut hll = HyperLogLog::with_precision(precision);
+                hll.extend(0..n);
+                assert_close(&hll, n);
+            }
+        }
+    }
+
+    #[test]
+    fn hll_merge() {
+        let mut a: HyperLogLog<u64> = (0..60_000).collect();
+        let b: HyperLogLog<u64> = (40_000..100_000).collect();
+        let small: HyperLogLog<u64> = (99_900..100_100).collect();
+        a.merge(b);
+        a.merge(small);
+        assert_close(&a, 100_100);
+
+        let all: HyperLogLog<u64> = (0..100_100).collect();
+        assert_eq!(a, all);
+
+        let mut sparse: HyperLogLog<u64> = (0..100).collect();
+        sparse.merge((50..150).collect());
+        assert_eq!(sparse.cardinality(), 150);
+        sparse.merge(HyperLogLog::default());
+        assert_eq!(sparse.cardinality(), 150);
+    }
+
+    #[test]
+    fn hll_serde() {
+        // The last sketch has unmerged entries past the sparse limit.
+        for n in [100, 10_000, 1_100] {
+            let hll: HyperLogLog<u64> = (0..n).collect();
+            let json = serde_json::to_string(&hll).unwrap();
+            let back: HyperLogLog<u64> = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, hll);
+        }
+    }
+
+    #[test]
+    fn hll_serde_invalid() {
+        for json in [
+            r#"{"precision":70,"sparse":[],"registers":[]}"#,
+            r#"{"precision":4,"sparse":[],"registers":[0,1,2]}"#,
+            r#"{"precision":4,"sparse":[],"registers":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,99]}"#,
+            r#"{"precision":12,"sparse":[129,65],"registers":[]}"#,
+            r#"{"precision":12,"sparse":[65,66],"registers":[]}"#,
+            r#"{"precision":12,"sparse":[4294967233],"registers":[]}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<HyperLogLog<u64>>(json).is_err(),
+                "{json}"
+            );
+        }
+        let valid = r#"{"precision":12,"sparse":[65,129],"registers":[]}"#;
+        let hll: HyperLogLog<u64> = serde_json::from_str(valid).unwrap();
+        assert_eq!(hll.cardinality(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "different precisions")]
+    fn hll_merge_precision_mismatch() {
+        let mut a: HyperLogLog<u64> = HyperLogLog::with_precision(10);
+        a.add(&1);
+        let mut b = HyperLogLog::with_precision(12);
+        b.add(&2);
+        a.merge(b);
+    }
+}
@@ -8,31 +8,100 @@
 
 use num_traits::ToPrimitive;
 use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, HashMap};
 use std::hash;
 
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-pub use frequency::{Frequencies, UniqueValues};
-pub use minmax::MinMax;
-pub use online::{mean, stddev, variance, OnlineStats};
-pub use unsorted::{antimodes, mad, median, mode, modes, quartiles, Unsorted};
+// Lets the derive macro's `::stats::Commute` paths resolve in this crate.
+#[cfg(feature = "derive")]
+extern crate self as stats;
+
+pub use countmin::CountMinSketch;
+pub use covariance::{CovarianceMatrix, OnlineCovariance};
+pub use ewm::Ewm;
+pub use frequency::{Frequencies, SortedFrequencies, UniqueValues};
+pub use histogram::{BinRule, Histogram};
+pub use hyperloglog::{approx_cardinality, HyperLogLog};
+pub use minmax::{MinMax, MultisetMinMax};
+pub use online::{mean, stddev, variance, MeanStats, OnlineStats, SumStats};
+pub use outliers::{outlier_indices, Fences, OutlierRule, Outliers, Severity};
+/// Derives `Commute` for a struct by merging each of its fields.
+#[cfg(feature = "derive")]
+pub use qsv_stats_derive::Commute;
+pub use reservoir::Reservoir;
+pub use rolling::{rolling, Rolling, RollingIter, RollingSummary};
+pub use sorted::Sorted;
+pub use tdigest::{approx_quantile, TDigest};
+pub use topk::{CountEstimate, TopK};
+pub use unsorted::{
+    antimodes, mad, median, mode, modes, percentile, quartiles, QuantileMethod, Unsorted,
+};
+
+/// How accumulators treat values that are not ordered with respect to
+/// themselves, i.e. `NaN`.
+///
+/// Infinities are ordered, so they are always kept. Merging accumulators
+/// with different policies panics, unless one of them is empty, in which case
+/// the result takes the policy of the other.
+///
+/// Before 0.19, `NaN`s were compared with `partial_cmp`, so results
+/// depended on the order of the samples (e.g. `MinMax` kept a `NaN` only if
+/// it came first). The default is now `TotalOrder`, which makes the maximum
+/// of `[1.0, NaN, 3.0]` be `NaN`; use `Skip` to leave `NaN`s out instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NanPolicy {
+    /// Ignore `NaN`s. They are counted, but do not count as data points.
+    Skip,
+    /// Keep `NaN`s, and make any statistic that depends on their value `NaN`.
+    Propagate,
+    /// Keep `NaN`s, ordered after every other value (like `f64::total_cmp`
+    /// orders positive `NaN`s).
+    #[default]
+    TotalOrder,
+}
+
+/// Returns true if `x` is not ordered with respect to itself, e.g. `NaN`.
+#[inline]
+pub(crate) fn is_nan<T: PartialOrd>(x: &T) -> bool {
+    #[allow(clippy::eq_op)]
+    x.partial_cmp(x).is_none()
+}
+
+/// A total order for `PartialOrd` types, where values that are not ordered
+/// with respect to themselves (`NaN`s) are equal to each other and greater
+/// than every other value.
+#[inline]
+pub(crate) fn total_cmp<T: PartialOrd>(a: &T, b: &T) -> Ordering {
+    a.partial_cmp(b)
+        .unwrap_or_else(|| is_nan(a).cmp(&is_nan(b)))
+}
 
 /// Partial wraps a type that satisfies `PartialOrd` and implements `Ord`.
 ///
 /// This allows types like `f64` to be used in data structures that require
-/// `Ord`. When an ordering is not defined, an arbitrary order is returned.
-#[derive(Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+/// `Ord`. `NaN`s are ordered after every other value, as in `total_cmp`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 struct Partial<T>(pub T);
 
 impl<T: PartialEq> Eq for Partial<T> {}
 unsafe impl<T> Send for Partial<T> {}
 unsafe impl<T> Sync for Partial<T> {}
 
-#[allow(clippy::derive_ord_xor_partial_ord)]
+// Sorting and selection use `PartialOrd`'s comparison operators, so they
+// must agree with `Ord`.
+impl<T: PartialOrd> PartialOrd for Partial<T> {
+    #[inline]
+    fn partial_cmp(&self, other: &Partial<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl<T: PartialOrd> Ord for Partial<T> {
     #[inline]
     fn cmp(&self, other: &Partial<T>) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Less)
+        total_cmp(&self.0, &other.0)
     }
 }
 
@@ -97,6 +166,27 @@ impl<T: hash::Hash> hash::Hash for Partial<T> {
     }
 }
 
+/// The number of samples each task handles in the `par_from_slice`
+/// constructors.
+pub(crate) const PAR_CHUNK_SIZE: usize = 1 << 14;
+
+/// Hashes a value with a fixed-key hasher.
+///
+/// This is SipHash-2-4 with fixed keys. Unlike `ahash`, the result does not
+/// depend on the process, so sketches built from these hashes can be
+/// persisted and merged later. It does depend on how `T` feeds the hasher,
+/// which the standard library does not guarantee across Rust versions, and
+/// which differs across pointer widths and endiannesses for types like
+/// `usize`. Persisted sketches should only be merged by builds with the same
+/// toolchain and target.
+#[inline]
+pub(crate) fn hash64<T: hash::Hash + ?Sized>(v: &T, seed: u64) -> u64 {
+    #[allow(deprecated)]
+    let mut hasher = hash::SipHasher::new_with_keys(seed, 0x5153_5653_5441_5453);
+    v.hash(&mut hasher);
+    hash::Hasher::finish(&hasher)
+}
+
 /// Defines an interface for types that have an identity and can be commuted.
 ///
 /// The value returned by `Default::default` must be its identity with respect
@@ -114,6 +204,17 @@ pub trait Commute: Sized {
     }
 }
 
+/// Defines an interface for types whose `merge` can be undone, so that
+/// summaries can be maintained as data is deleted or updated.
+///
+/// After `a.merge(b.clone())`, `a.subtract(&b)` must restore `a`, up to
+/// floating point rounding. Subtracting a value that was never merged in
+/// leaves `self` in an unspecified (but memory safe) state.
+pub trait Subtract: Commute {
+    /// Removes the data points in `other` from `self`.
+    fn subtract(&mut self, other: &Self);
+}
+
 /// Merges all items in the stream.
 ///
 /// If the stream is empty, `None` is returned.
@@ -128,6 +229,24 @@ pub fn merge_all<T: Commute, I: Iterator<Item = T>>(mut it: I) -> Option<T> {
     )
 }
 
+/// Merges all items in the parallel stream.
+///
+/// The items are merged pairwise as a tree across the rayon thread pool,
+/// so this is only deterministic for types whose merge is exact.
+///
+/// If the stream is empty, `None` is returned.
+#[inline]
+pub fn par_merge_all<T, I>(it: I) -> Option<T>
+where
+    T: Commute + Send,
+    I: IntoParallelIterator<Item = T>,
+{
+    it.into_par_iter().reduce_with(|mut v1, v2| {
+        v1.merge(v2);
+        v1
+    })
+}
+
 impl<T: Commute> Commute for Option<T> {
     #[inline]
     fn merge(&mut self, other: Option<T>) {
@@ -176,15 +295,78 @@ impl<T: Commute> Commute for Vec<T> {
     }
 }
 
+impl<K, V, S> Commute for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    V: Commute,
+    S: hash::BuildHasher,
+{
+    /// Merges values with the same key, and adds keys missing from `self`.
+    #[inline]
+    fn merge(&mut self, other: HashMap<K, V, S>) {
+        for (k, v2) in other {
+            match self.entry(k) {
+                Entry::Occupied(mut e) => e.get_mut().merge(v2),
+                Entry::Vacant(e) => {
+                    e.insert(v2);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Commute, const N: usize> Commute for [T; N] {
+    #[inline]
+    fn merge(&mut self, other: [T; N]) {
+        for (v1, v2) in self.iter_mut().zip(other) {
+            v1.merge(v2);
+        }
+    }
+}
+
+macro_rules! commute_tuple {
+    ($($name:ident $idx:tt),+) => {
+        impl<$($name: Commute),+> Commute for ($($name,)+) {
+            #[inline]
+            fn merge(&mut self, other: ($($name,)+)) {
+                $(self.$idx.merge(other.$idx);)+
+            }
+        }
+    };
+}
+
+commute_tuple!(A 0);
+commute_tuple!(A 0, B 1);
+commute_tuple!(A 0, B 1, C 2);
+commute_tuple!(A 0, B 1, C 2, D 3);
+commute_tuple!(A 0, B 1, C 2, D 3, E 4);
+commute_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+commute_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+commute_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+mod countmin;
+mod covariance;
+mod ewm;
 mod frequency;
+mod histogram;
+mod hyperloglog;
 mod minmax;
 mod online;
+mod outliers;
+mod reservoir;
+mod rng;
+mod rolling;
+mod sorted;
+mod special;
+mod tdigest;
+mod topk;
 mod unsorted;
 
 #[cfg(test)]
 mod test {
     use crate::unsorted::Unsorted;
-    use crate::Commute;
+    use crate::{merge_all, par_merge_all, Commute, Frequencies, MinMax, OnlineStats};
+    use std::collections::HashMap;
 
     #[test]
     fn options() {
@@ -194,4 +376,41 @@ mod test {
         merged.merge(Some(v2));
         assert_eq!(merged.unwrap().mode(), Some(5));
     }
+
+    #[test]
+    fn par_merge() {
+        let parts: Vec<MinMax<u32>> = (0..100u32)
+            .map(|i| vec![i * 7 % 101, i * 13 % 103].into_iter().collect())
+            .collect();
+        let sequential = merge_all(parts.clone().into_iter()).unwrap();
+        let parallel = par_merge_all(parts).unwrap();
+        assert_eq!(parallel, sequential);
+        assert_eq!(parallel.len(), 200);
+        assert_eq!(par_merge_all(Vec::<MinMax<u32>>::new()), None);
+    }
+
+    #[test]
+    fn containers() {
+        let mut tuple: (OnlineStats, MinMax<u32>) = (
+            vec![1, 2].into_iter().collect(),
+            vec![1, 2].into_iter().collect(),
+        );
+        tuple.merge((vec![3].into_iter().collect(), vec![9].into_iter().collect()));
+        assert_eq!(tuple.0.mean(), 2.0);
+        assert_eq!(tuple.1.max(), Some(&9));
+
+        let mut array: [MinMax<u32>; 2] = [vec![5].into_iter().collect(), MinMax::new()];
+        array.merge([vec![1].into_iter().collect(), vec![7].into_iter().collect()]);
+        assert_eq!(array[0].min(), Some(&1));
+        assert_eq!(array[1].min(), Some(&7));
+
+        let mut columns: HashMap<&str, Frequencies<u32>> = HashMap::new();
+        columns.insert("a", vec![1, 1].into_iter().collect());
+        let mut other = HashMap::new();
+        other.insert("a", vec![1, 2].into_iter().collect());
+        other.insert("b", vec![3].into_iter().collect());
+        columns.merge(other);
+        assert_eq!(columns["a"].count(&1), 3);
+        assert_eq!(columns["b"].count(&3), 1);
+    }
 }
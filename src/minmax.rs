@@ -1,17 +1,32 @@
+use std::cmp::Ordering;
+use std::collections::btree_map::{BTreeMap, Entry};
 use std::fmt;
 
-use serde::{Deserialize, Serialize};
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSlice;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::Commute;
+use crate::{is_nan, total_cmp, Commute, NanPolicy, Partial, Subtract, PAR_CHUNK_SIZE};
 
 /// A commutative data structure for tracking minimum and maximum values.
 ///
 /// This also stores the number of samples.
+///
+/// `NaN`s are handled according to the `NanPolicy`: with `Propagate`, any
+/// `NaN` makes both the minimum and maximum `NaN`, and with `TotalOrder`
+/// (the default), a `NaN` is only the maximum.
+///
+/// Samples cannot be removed, since only the extremes are kept. Use
+/// `MultisetMinMax` when they need to be.
 #[derive(Clone, Deserialize, Serialize, Eq, PartialEq)]
 pub struct MinMax<T> {
     len: u64,
     min: Option<T>,
     max: Option<T>,
+    #[serde(default)]
+    nan_policy: NanPolicy,
+    #[serde(default)]
+    nans: u64,
 }
 
 impl<T: PartialOrd + Clone> MinMax<T> {
@@ -21,18 +36,104 @@ impl<T: PartialOrd + Clone> MinMax<T> {
         Default::default()
     }
 
+    /// Create an empty state that handles `NaN`s according to `nan_policy`.
+    #[must_use]
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> MinMax<T> {
+        MinMax {
+            nan_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Create a state from `samples`, splitting the work across the rayon
+    /// thread pool.
+    #[must_use]
+    pub fn par_from_slice(samples: &[T]) -> MinMax<T>
+    where
+        T: Send + Sync,
+    {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(|chunk| chunk.iter().cloned().collect())
+            .reduce(MinMax::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+
     /// Add a sample to the data.
     #[inline]
     pub fn add(&mut self, sample: T) {
+        // A sample that is ordered with respect to both extremes is not
+        // `NaN`, so the policy only matters when a comparison fails (or for
+        // the first sample).
+        if let (Some(min), Some(max)) = (&self.min, &self.max) {
+            let (below, above) = (sample.partial_cmp(min), sample.partial_cmp(max));
+            if below.is_some() && above.is_some() {
+                self.len += 1;
+                if below == Some(Ordering::Less) {
+                    self.min = Some(sample);
+                } else if above == Some(Ordering::Greater) {
+                    self.max = Some(sample);
+                }
+                return;
+            }
+        }
+        self.add_unordered(sample);
+    }
+
+    /// Adds the first sample, or one that is not ordered with respect to
+    /// one of the extremes, i.e. where either is `NaN`.
+    #[inline]
+    fn add_unordered(&mut self, sample: T) {
+        if is_nan(&sample) {
+            self.nans += 1;
+            match self.nan_policy {
+                NanPolicy::Skip => return,
+                NanPolicy::Propagate => {
+                    self.len += 1;
+                    self.min = Some(sample.clone());
+                    self.max = Some(sample);
+                    return;
+                }
+                NanPolicy::TotalOrder => {}
+            }
+        } else if self.nan_policy == NanPolicy::Propagate && self.nans > 0 {
+            self.len += 1;
+            return;
+        }
         self.len += 1;
-        if self.min.as_ref().map_or(true, |v| &sample < v) {
+        if self
+            .min
+            .as_ref()
+            .map_or(true, |v| total_cmp(&sample, v).is_lt())
+        {
             self.min = Some(sample.clone());
         }
-        if self.max.as_ref().map_or(true, |v| &sample > v) {
+        if self
+            .max
+            .as_ref()
+            .map_or(true, |v| total_cmp(&sample, v).is_gt())
+        {
             self.max = Some(sample);
         }
     }
 
+    /// Returns the policy for `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Returns the number of `NaN`s added, which were not counted as data
+    /// points if the policy is `NanPolicy::Skip`.
+    #[inline]
+    #[must_use]
+    pub const fn nan_count(&self) -> usize {
+        self.nans as usize
+    }
+
     /// Returns the minimum of the data set.
     ///
     /// `None` is returned if and only if the number of samples is `0`.
@@ -69,12 +170,45 @@ impl<T: PartialOrd + Clone> MinMax<T> {
 impl<T: PartialOrd> Commute for MinMax<T> {
     #[inline]
     fn merge(&mut self, v: MinMax<T>) {
+        if v.len == 0 && v.nans == 0 {
+            return;
+        }
+        if self.len == 0 && self.nans == 0 {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.nan_policy, v.nan_policy,
+            "cannot merge MinMax with different NaN policies"
+        );
+        let propagated = self.nan_policy == NanPolicy::Propagate && self.nans > 0;
         self.len += v.len;
-        if self.min.is_none() || (v.min.is_some() && v.min < self.min) {
-            self.min = v.min;
+        self.nans += v.nans;
+        if propagated {
+            return;
         }
-        if self.max.is_none() || (v.max.is_some() && v.max > self.max) {
+        if self.nan_policy == NanPolicy::Propagate && v.nans > 0 {
+            self.min = v.min;
             self.max = v.max;
+            return;
+        }
+        if let Some(min) = v.min {
+            if self
+                .min
+                .as_ref()
+                .map_or(true, |m| total_cmp(&min, m).is_lt())
+            {
+                self.min = Some(min);
+            }
+        }
+        if let Some(max) = v.max {
+            if self
+                .max
+                .as_ref()
+                .map_or(true, |m| total_cmp(&max, m).is_gt())
+            {
+                self.max = Some(max);
+            }
         }
     }
 }
@@ -86,6 +220,8 @@ impl<T: PartialOrd> Default for MinMax<T> {
             len: 0,
             min: None,
             max: None,
+            nan_policy: NanPolicy::default(),
+            nans: 0,
         }
     }
 }
@@ -113,6 +249,21 @@ impl<T: PartialOrd + Clone> FromIterator<T> for MinMax<T> {
     }
 }
 
+impl<T: PartialOrd + Clone + Send> FromParallelIterator<T> for MinMax<T> {
+    #[inline]
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(it: I) -> MinMax<T> {
+        it.into_par_iter()
+            .fold(MinMax::new, |mut v, sample| {
+                v.add(sample);
+                v
+            })
+            .reduce(MinMax::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+}
+
 impl<T: PartialOrd + Clone> Extend<T> for MinMax<T> {
     #[inline]
     fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
@@ -122,10 +273,199 @@ impl<T: PartialOrd + Clone> Extend<T> for MinMax<T> {
     }
 }
 
+/// A commutative data structure for tracking minimum and maximum values
+/// that also supports removing samples.
+///
+/// Unlike `MinMax`, this is backed by a multiset: it keeps a count of every
+/// distinct value, in a `BTreeMap`. So it takes `O(log d)` time per sample
+/// and `O(d)` space, where `d` is the number of distinct values, but the
+/// minimum and maximum stay correct when samples are removed with `remove`
+/// or `subtract`.
+///
+/// `NaN`s are ordered after every other value.
+///
+/// This serializes as a sequence of `(value, count)` pairs in ascending
+/// order.
+#[derive(Clone)]
+pub struct MultisetMinMax<T> {
+    len: u64,
+    counts: BTreeMap<Partial<T>, u64>,
+}
+
+impl<T: PartialOrd> MultisetMinMax<T> {
+    /// Create an empty state where min and max values do not exist.
+    #[must_use]
+    pub fn new() -> MultisetMinMax<T> {
+        Default::default()
+    }
+
+    /// Add a sample to the data.
+    #[inline]
+    pub fn add(&mut self, sample: T) {
+        self.add_n(sample, 1);
+    }
+
+    /// Add `count` occurrences of a sample to the data.
+    #[inline]
+    pub fn add_n(&mut self, sample: T, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.len += count;
+        *self.counts.entry(Partial(sample)).or_insert(0) += count;
+    }
+
+    /// Remove one occurrence of a sample from the data.
+    ///
+    /// Returns false if `sample` was not in the data.
+    #[inline]
+    pub fn remove(&mut self, sample: T) -> bool {
+        self.remove_n(sample, 1)
+    }
+
+    /// Remove up to `count` occurrences of a sample from the data.
+    ///
+    /// Returns false if `sample` was not in the data.
+    #[inline]
+    pub fn remove_n(&mut self, sample: T, count: u64) -> bool {
+        let Entry::Occupied(mut entry) = self.counts.entry(Partial(sample)) else {
+            return false;
+        };
+        let removed = count.min(*entry.get());
+        self.len -= removed;
+        *entry.get_mut() -= removed;
+        if *entry.get() == 0 {
+            entry.remove();
+        }
+        true
+    }
+
+    /// Returns the minimum of the data set.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    #[inline]
+    #[must_use]
+    pub fn min(&self) -> Option<&T> {
+        self.counts.keys().next().map(|v| &v.0)
+    }
+
+    /// Returns the maximum of the data set.
+    ///
+    /// `None` is returned if and only if the number of samples is `0`.
+    #[inline]
+    #[must_use]
+    pub fn max(&self) -> Option<&T> {
+        self.counts.keys().next_back().map(|v| &v.0)
+    }
+
+    /// Returns the number of distinct values.
+    #[inline]
+    #[must_use]
+    pub fn cardinality(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if there are no data points.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: PartialOrd> Commute for MultisetMinMax<T> {
+    #[inline]
+    fn merge(&mut self, v: MultisetMinMax<T>) {
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        for (Partial(sample), count) in v.counts {
+            self.add_n(sample, count);
+        }
+    }
+}
+
+impl<T: PartialOrd + Clone> Subtract for MultisetMinMax<T> {
+    #[inline]
+    fn subtract(&mut self, v: &MultisetMinMax<T>) {
+        for (sample, &count) in &v.counts {
+            self.remove_n(sample.0.clone(), count);
+        }
+    }
+}
+
+impl<T: PartialOrd> Default for MultisetMinMax<T> {
+    #[inline]
+    fn default() -> MultisetMinMax<T> {
+        MultisetMinMax {
+            len: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MultisetMinMax<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let min = self.counts.keys().next();
+        let max = self.counts.keys().next_back();
+        match (min, max) {
+            (Some(min), Some(max)) => {
+                write!(f, "[{:?}, {:?}]", min.0, max.0)
+            }
+            _ => write!(f, "N/A"),
+        }
+    }
+}
+
+impl<T: PartialOrd> FromIterator<T> for MultisetMinMax<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> MultisetMinMax<T> {
+        let mut v = MultisetMinMax::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: PartialOrd> Extend<T> for MultisetMinMax<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for MultisetMinMax<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.counts.iter().map(|(v, count)| (&v.0, count)))
+    }
+}
+
+impl<'de, T: PartialOrd + Deserialize<'de>> Deserialize<'de> for MultisetMinMax<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pairs = Vec::<(T, u64)>::deserialize(deserializer)?;
+        let mut minmax = MultisetMinMax::new();
+        for (sample, count) in pairs {
+            minmax.add_n(sample, count);
+        }
+        Ok(minmax)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::MinMax;
-    use crate::Commute;
+    use super::{MinMax, MultisetMinMax};
+    use crate::{Commute, NanPolicy, Subtract};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
     #[test]
     fn minmax() {
@@ -150,4 +490,124 @@ mod test {
         assert_eq!(mx1.min(), Some(&1u32));
         assert_eq!(mx1.max(), Some(&10u32));
     }
+
+    #[test]
+    fn minmax_nan() {
+        let data = [3.0, f64::NAN, 1.0, 2.0];
+
+        let minmax: MinMax<f64> = data.iter().copied().collect();
+        assert_eq!(minmax.min(), Some(&1.0));
+        assert!(minmax.max().unwrap().is_nan());
+        assert_eq!((minmax.len(), minmax.nan_count()), (4, 1));
+
+        let mut skip = MinMax::with_nan_policy(NanPolicy::Skip);
+        skip.extend(data.iter().copied());
+        assert_eq!((skip.min(), skip.max()), (Some(&1.0), Some(&3.0)));
+        assert_eq!((skip.len(), skip.nan_count()), (3, 1));
+
+        let mut propagate = MinMax::with_nan_policy(NanPolicy::Propagate);
+        propagate.extend(data.iter().copied());
+        assert!(propagate.min().unwrap().is_nan());
+        assert!(propagate.max().unwrap().is_nan());
+        assert_eq!(propagate.len(), 4);
+
+        // `NaN`s after the extremes are set, and samples after a `NaN`.
+        let later = [3.0, 5.0, 4.0, f64::NAN, 4.5, 1.0, 6.0];
+        let total: MinMax<f64> = later.iter().copied().collect();
+        assert_eq!(total.min(), Some(&1.0));
+        assert!(total.max().unwrap().is_nan());
+        let mut skipped = MinMax::with_nan_policy(NanPolicy::Skip);
+        skipped.extend(later);
+        assert_eq!((skipped.min(), skipped.max()), (Some(&1.0), Some(&6.0)));
+        assert_eq!((skipped.len(), skipped.nan_count()), (6, 1));
+
+        // Merging propagates `NaN`s from either side.
+        let mut left = MinMax::with_nan_policy(NanPolicy::Propagate);
+        left.extend([5.0, 0.0]);
+        let mut right = MinMax::with_nan_policy(NanPolicy::Propagate);
+        right.extend([f64::NAN, 7.0]);
+        left.merge(right);
+        assert!(left.min().unwrap().is_nan());
+        assert_eq!((left.len(), left.nan_count()), (4, 1));
+
+        // Only `NaN`s were added, so there are no data points.
+        let mut nans = MinMax::with_nan_policy(NanPolicy::Skip);
+        nans.add(f64::NAN);
+        assert!(nans.is_empty());
+        assert_eq!(nans.min(), None);
+        skip.merge(nans);
+        assert_eq!((skip.min(), skip.max()), (Some(&1.0), Some(&3.0)));
+        assert_eq!(skip.nan_count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "different NaN policies")]
+    fn minmax_merge_policy_mismatch() {
+        let mut a = MinMax::with_nan_policy(NanPolicy::Skip);
+        a.add(1.0);
+        let mut b = MinMax::new();
+        b.add(2.0);
+        a.merge(b);
+    }
+
+    #[test]
+    fn minmax_parallel() {
+        let minmax: MinMax<u64> = (0..10_000u64)
+            .into_par_iter()
+            .map(|i| (i * 7_919) % 10_007)
+            .collect();
+        assert_eq!(minmax.len(), 10_000);
+        assert_eq!(minmax.min(), Some(&0));
+        assert_eq!(minmax.max(), Some(&10_006));
+
+        let data: Vec<f64> = (0..40_000)
+            .map(|i| f64::from((i * 7_919) % 10_007))
+            .collect();
+        let from_slice = MinMax::par_from_slice(&data);
+        assert_eq!(from_slice.len(), 40_000);
+        assert_eq!(
+            (from_slice.min(), from_slice.max()),
+            (Some(&0.0), Some(&10_006.0))
+        );
+        assert!(MinMax::<f64>::par_from_slice(&[]).is_empty());
+    }
+
+    #[test]
+    fn multiset_remove() {
+        let mut minmax: MultisetMinMax<u32> = vec![4, 1, 9, 1, 7].into_iter().collect();
+        assert_eq!((minmax.min(), minmax.max()), (Some(&1), Some(&9)));
+        assert!(minmax.remove(9));
+        assert!(!minmax.remove(9));
+        assert_eq!(minmax.max(), Some(&7));
+        assert!(minmax.remove(1));
+        assert_eq!(minmax.min(), Some(&1));
+        assert!(minmax.remove(1));
+        assert_eq!(minmax.min(), Some(&4));
+        assert_eq!((minmax.len(), minmax.cardinality()), (2, 2));
+        assert!(minmax.remove_n(4, 10));
+        assert!(minmax.remove(7));
+        assert!(minmax.is_empty());
+        assert_eq!(minmax.min(), None);
+    }
+
+    #[test]
+    fn multiset_subtract() {
+        let mut total: MultisetMinMax<f64> = vec![2.0, 5.0, f64::NAN].into_iter().collect();
+        let part: MultisetMinMax<f64> = vec![-1.0, 5.0, 8.0].into_iter().collect();
+        total.merge(part.clone());
+        assert_eq!(total.min(), Some(&-1.0));
+        assert!(total.max().unwrap().is_nan());
+        total.subtract(&part);
+        total.remove(f64::NAN);
+        assert_eq!((total.min(), total.max()), (Some(&2.0), Some(&5.0)));
+        assert_eq!(total.len(), 2);
+
+        let json = serde_json::to_string(&total).unwrap();
+        assert_eq!(json, "[[2.0,1],[5.0,1]]");
+        let back: MultisetMinMax<f64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            (back.min(), back.max(), back.len()),
+            (Some(&2.0), Some(&5.0), 2)
+        );
+    }
 }
@@ -1,9 +1,11 @@
 use std::fmt;
 
 use num_traits::ToPrimitive;
-use serde::{Deserialize, Serialize};
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSlice;
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::Commute;
+use crate::{special, Commute, NanPolicy, Subtract, PAR_CHUNK_SIZE};
 
 /// Compute the standard deviation of a stream in constant space.
 pub fn stddev<'a, I, T>(x: I) -> f64
@@ -35,12 +37,84 @@ where
     mean(it)
 }
 
-/// Online state for computing mean, variance and standard deviation.
+/// Online state for computing mean, variance, standard deviation, skewness
+/// and kurtosis.
+///
+/// See `SumStats` for an exact or compensated sum, and `MeanStats` for the
+/// geometric and harmonic means. They are kept separately so that `add` does
+/// not pay for them.
+///
+/// Samples may be weighted with `add_weighted`. The estimators then treat the
+/// sum of the weights as the population size, i.e. they interpret weights as
+/// frequency weights, except for `reliability_variance`.
+///
+/// `NaN` samples make every statistic `NaN`, unless the `NanPolicy` is
+/// `Skip`. (`Propagate` and `TotalOrder` are the same here, since no
+/// statistic depends on the order of the samples.)
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(from = "RawOnlineStats")]
 pub struct OnlineStats {
     size: u64,
+    /// The sum of the weights, which is `size` if all weights are `1`.
+    weight: f64,
+    /// The sum of the squared weights.
+    weight2: f64,
+    nan_policy: NanPolicy,
+    nans: u64,
     mean: f64,
     q: f64,
+    m3: f64,
+    m4: f64,
+}
+
+/// The serialized form of `OnlineStats`.
+///
+/// Snapshots from before the weights and higher moments were tracked
+/// only have `size`, `mean` and `q`, so the other fields may be missing.
+#[derive(Deserialize)]
+struct RawOnlineStats {
+    size: u64,
+    #[serde(default, deserialize_with = "present")]
+    weight: Option<f64>,
+    #[serde(default, deserialize_with = "present")]
+    weight2: Option<f64>,
+    #[serde(default)]
+    nan_policy: NanPolicy,
+    #[serde(default)]
+    nans: u64,
+    mean: f64,
+    q: f64,
+    #[serde(default, deserialize_with = "present")]
+    m3: Option<f64>,
+    #[serde(default, deserialize_with = "present")]
+    m4: Option<f64>,
+}
+
+/// Deserializes a field that is present, leaving `None` for a missing one.
+fn present<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+impl From<RawOnlineStats> for OnlineStats {
+    fn from(raw: RawOnlineStats) -> OnlineStats {
+        // Older snapshots only had unit weights, so their weights follow
+        // from `size`. The higher moments are unknown unless there were no
+        // samples.
+        let unknown = if raw.size == 0 { 0.0 } else { f64::NAN };
+        OnlineStats {
+            size: raw.size,
+            weight: raw.weight.unwrap_or(raw.size as f64),
+            weight2: raw.weight2.unwrap_or(raw.size as f64),
+            nan_policy: raw.nan_policy,
+            nans: raw.nans,
+            mean: raw.mean,
+            q: raw.q,
+            m3: raw.m3.unwrap_or(unknown),
+            m4: raw.m4.unwrap_or(unknown),
+        }
+    }
 }
 
 impl OnlineStats {
@@ -52,10 +126,40 @@ impl OnlineStats {
         Default::default()
     }
 
+    /// Create initial state that handles `NaN`s according to `nan_policy`.
+    #[must_use]
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> OnlineStats {
+        OnlineStats {
+            nan_policy,
+            ..Default::default()
+        }
+    }
+
     /// Initializes variance from a sample.
     #[must_use]
     pub fn from_slice<T: ToPrimitive>(samples: &[T]) -> OnlineStats {
-        samples.iter().map(|n| n.to_f64().unwrap()).collect()
+        let mut stats = OnlineStats::new();
+        for sample in samples {
+            stats.add(sample);
+        }
+        stats
+    }
+
+    /// Initializes variance from a sample, splitting the work across the
+    /// rayon thread pool.
+    ///
+    /// The result can differ from `from_slice` in the last few bits of the
+    /// floating point estimates, since the chunks are merged in a different
+    /// order.
+    #[must_use]
+    pub fn par_from_slice<T: ToPrimitive + Sync>(samples: &[T]) -> OnlineStats {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(OnlineStats::from_slice)
+            .reduce(OnlineStats::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
     }
 
     /// Return the current mean.
@@ -65,36 +169,255 @@ impl OnlineStats {
     }
 
     /// Return the current standard deviation.
+    ///
+    /// This is the population standard deviation. See `sample_stddev` for
+    /// the Bessel-corrected estimator.
     #[must_use]
     pub fn stddev(&self) -> f64 {
         self.variance().sqrt()
     }
 
     /// Return the current variance.
+    ///
+    /// This is the population variance (i.e. divided by `n`). See
+    /// `sample_variance` for the Bessel-corrected estimator.
     // TODO: look into alternate algorithms for calculating variance
     // https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance
     #[must_use]
     pub fn variance(&self) -> f64 {
-        self.q / (self.size as f64)
+        self.population_variance()
+    }
+
+    /// Return the current population variance (divided by `n`).
+    #[must_use]
+    pub fn population_variance(&self) -> f64 {
+        self.q / self.weight
+    }
+
+    /// Return the current population standard deviation.
+    #[must_use]
+    pub fn population_stddev(&self) -> f64 {
+        self.population_variance().sqrt()
+    }
+
+    /// Return the current sample variance (divided by `n - 1`).
+    ///
+    /// This is the Bessel-corrected estimator, e.g. what `VAR.S` returns in
+    /// spreadsheets.
+    ///
+    /// For weighted samples, `n` is the sum of the weights, which is correct
+    /// for frequency weights (e.g. counts of repeated values). It is `NaN`
+    /// when `n` is at most `1`, which with fractional weights can happen for
+    /// any number of samples; use `reliability_variance` for those.
+    #[must_use]
+    pub fn sample_variance(&self) -> f64 {
+        if self.weight <= 1.0 {
+            return f64::NAN;
+        }
+        self.q / (self.weight - 1.0)
+    }
+
+    /// Return the current unbiased sample variance for reliability weights.
+    ///
+    /// With weights `w_i`, this divides by `W - sum(w_i^2) / W` where `W` is
+    /// the sum of the weights, so it does not depend on the scale of the
+    /// weights. It is the same as `sample_variance` when all weights are `1`,
+    /// and `NaN` when there are fewer than two samples.
+    #[must_use]
+    pub fn reliability_variance(&self) -> f64 {
+        if self.size < 2 {
+            return f64::NAN;
+        }
+        self.q / (self.weight - self.weight2 / self.weight)
+    }
+
+    /// Return the current sample standard deviation.
+    ///
+    /// This is the square root of `sample_variance`, e.g. what `STDEV.S`
+    /// returns in spreadsheets.
+    #[must_use]
+    pub fn sample_stddev(&self) -> f64 {
+        self.sample_variance().sqrt()
+    }
+
+    /// Return the standard error of the mean.
+    ///
+    /// This is the sample standard deviation divided by `sqrt(n)`.
+    #[must_use]
+    pub fn standard_error(&self) -> f64 {
+        self.sample_stddev() / self.weight.sqrt()
+    }
+
+    /// Return a two-sided confidence interval for the mean as `(lower, upper)`.
+    ///
+    /// `confidence` is the confidence level, e.g. `0.95` for a 95% interval.
+    /// The interval is based on Student's t-distribution with `n - 1`
+    /// degrees of freedom.
+    ///
+    /// `None` is returned if there are fewer than two samples or if
+    /// `confidence` is not in `(0, 1)`.
+    #[must_use]
+    pub fn mean_confidence_interval(&self, confidence: f64) -> Option<(f64, f64)> {
+        if self.weight <= 1.0 || !(confidence > 0.0 && confidence < 1.0) {
+            return None;
+        }
+        let df = self.weight - 1.0;
+        let t = special::student_t_quantile(0.5 + confidence / 2.0, df);
+        let margin = t * self.standard_error();
+        Some((self.mean - margin, self.mean + margin))
+    }
+
+    /// Return the current (population) skewness.
+    ///
+    /// This is the Fisher-Pearson coefficient of skewness `g1`, i.e. the
+    /// third central moment divided by the variance raised to `3/2`.
+    #[must_use]
+    pub fn skewness(&self) -> f64 {
+        self.weight.sqrt() * self.m3 / self.q.powf(1.5)
     }
 
-    // TODO: Calculate kurtosis
-    // also see https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance
+    /// Return the current (population) kurtosis.
+    ///
+    /// This is the fourth central moment divided by the squared variance,
+    /// which is `3` for a normal distribution.
+    #[must_use]
+    pub fn kurtosis(&self) -> f64 {
+        self.weight * self.m4 / (self.q * self.q)
+    }
+
+    /// Return the current (population) excess kurtosis.
+    ///
+    /// This is `kurtosis() - 3`, which is `0` for a normal distribution.
+    #[must_use]
+    pub fn excess_kurtosis(&self) -> f64 {
+        self.kurtosis() - 3.0
+    }
 
     /// Add a new sample.
     #[inline]
     pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
         let sample = sample.to_f64().unwrap();
+        if self.rejects(sample) {
+            return;
+        }
         // Taken from: https://en.wikipedia.org/wiki/Standard_deviation#Rapid_calculation_methods
         // See also: https://api.semanticscholar.org/CorpusID:120126049
         let oldmean = self.mean;
+        let n1 = self.weight;
         self.size += 1;
+        self.weight += 1.0;
+        self.weight2 += 1.0;
+        let n = self.weight;
         let delta = sample - oldmean;
-        self.mean += delta / (self.size as f64);
+        let delta_n = delta / n;
+        self.mean += delta_n;
         let delta2 = sample - self.mean;
+
+        // Higher-order moments must be updated before `q`, since they depend
+        // on its previous value.
+        // Taken from: https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Higher-order_statistics
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+        self.m4 += term1 * delta_n2 * n.mul_add(n, -3.0 * n + 3.0) + 6.0 * delta_n2 * self.q
+            - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.q;
+
         self.q += delta * delta2;
     }
 
+    /// Add a new sample with the given weight.
+    ///
+    /// This is West's weighted incremental algorithm, from West, D. H. D.
+    /// (1979), "Updating mean and variance estimates: an improved method",
+    /// extended to the higher moments. Adding a sample with weight `k` is
+    /// the same as adding it `k` times, up to rounding.
+    ///
+    /// Samples with a weight of `0` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    #[inline]
+    pub fn add_weighted<T: ToPrimitive>(&mut self, sample: &T, weight: f64) {
+        assert!(
+            weight >= 0.0 && weight.is_finite(),
+            "weight must be finite and non-negative"
+        );
+        if weight == 0.0 {
+            return;
+        }
+        let sample = sample.to_f64().unwrap();
+        if self.rejects(sample) {
+            return;
+        }
+        // A weighted sample is a population of its own with zero variance,
+        // so it is combined with the pairwise formulas.
+        self.merge(OnlineStats {
+            size: 1,
+            weight,
+            weight2: weight * weight,
+            mean: sample,
+            nan_policy: self.nan_policy,
+            ..OnlineStats::default()
+        });
+    }
+
+    /// Remove a sample that was previously added.
+    ///
+    /// This reverses `add`, so the statistics are the same as if `sample`
+    /// had never been added, up to rounding. A `NaN` that propagated stays,
+    /// even after it is removed.
+    #[inline]
+    pub fn remove<T: ToPrimitive>(&mut self, sample: &T) {
+        let mut point = OnlineStats::with_nan_policy(self.nan_policy);
+        point.add(sample);
+        self.subtract(&point);
+    }
+
+    /// Counts `NaN`s, and returns true if `sample` should be skipped.
+    #[inline]
+    fn rejects(&mut self, sample: f64) -> bool {
+        if sample.is_nan() {
+            self.nans += 1;
+            return self.nan_policy == NanPolicy::Skip;
+        }
+        false
+    }
+
+    /// Returns the policy for `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Returns the number of `NaN`s added, which were not counted as data
+    /// points if the policy is `NanPolicy::Skip`.
+    #[inline]
+    #[must_use]
+    pub const fn nan_count(&self) -> usize {
+        self.nans as usize
+    }
+
+    /// Return the current coefficient of variation.
+    ///
+    /// This is the (population) standard deviation divided by the mean, as
+    /// a ratio rather than a percentage. It is only meaningful for data on a
+    /// ratio scale, i.e. with a true zero, and is infinite or `NaN` if the
+    /// mean is `0`.
+    #[must_use]
+    pub fn cv(&self) -> f64 {
+        self.stddev() / self.mean
+    }
+
+    /// Returns the sum of the weights of the data points, which is `len()`
+    /// unless samples were added with `add_weighted`.
+    #[inline]
+    #[must_use]
+    pub const fn weight(&self) -> f64 {
+        self.weight
+    }
+
     /// Add a new NULL value to the population.
     ///
     /// This increases the population size by `1`.
@@ -121,11 +444,52 @@ impl OnlineStats {
 impl Commute for OnlineStats {
     #[inline]
     fn merge(&mut self, v: OnlineStats) {
+        if v.size == 0 && v.nans == 0 {
+            return;
+        }
+        if self.size == 0 && self.nans == 0 {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.nan_policy, v.nan_policy,
+            "cannot merge OnlineStats with different NaN policies"
+        );
+        if v.size == 0 {
+            self.nans += v.nans;
+            return;
+        }
+        if self.size == 0 {
+            let nans = self.nans;
+            *self = v;
+            self.nans += nans;
+            return;
+        }
+
         // Taken from: https://en.wikipedia.org/wiki/Standard_deviation#Combining_standard_deviations
-        let (s1, s2) = (self.size as f64, v.size as f64);
+        let (s1, s2) = (self.weight, v.weight);
         let meandiffsq = (self.mean - v.mean) * (self.mean - v.mean);
 
+        // Higher-order moments use the pairwise formulas from Pébay (2008),
+        // "Formulas for Robust, One-Pass Parallel Computation of Covariances
+        // and Arbitrary-Order Statistical Moments", and must be combined
+        // before `q` is updated.
+        let n = s1 + s2;
+        let delta = v.mean - self.mean;
+        let delta3 = delta * meandiffsq;
+        let delta4 = meandiffsq * meandiffsq;
+        self.m4 += v.m4
+            + delta4 * s1 * s2 * s1.mul_add(s1, s2.mul_add(s2, -s1 * s2)) / (n * n * n)
+            + 6.0 * meandiffsq * s1.mul_add(s1 * v.q, s2 * s2 * self.q) / (n * n)
+            + 4.0 * delta * s1.mul_add(v.m3, -s2 * self.m3) / n;
+        self.m3 += v.m3
+            + delta3 * s1 * s2 * (s1 - s2) / (n * n)
+            + 3.0 * delta * s1.mul_add(v.q, -s2 * self.q) / n;
+
         self.size += v.size;
+        self.weight += v.weight;
+        self.weight2 += v.weight2;
+        self.nans += v.nans;
 
         //self.mean = ((s1 * self.mean) + (s2 * v.mean)) / (s1 + s2);
         /*
@@ -138,12 +502,71 @@ impl Commute for OnlineStats {
     }
 }
 
+impl Subtract for OnlineStats {
+    /// Removes the data points in `v`, by solving the pairwise formulas in
+    /// `merge` for the other half.
+    #[inline]
+    fn subtract(&mut self, v: &OnlineStats) {
+        self.nans = self.nans.saturating_sub(v.nans);
+        if v.size == 0 {
+            return;
+        }
+        assert!(
+            v.size <= self.size,
+            "cannot subtract more data points than were added"
+        );
+        // Compare the weights rather than the sizes, since the samples may
+        // have been added with other weights than they are removed with.
+        if v.weight >= self.weight {
+            *self = OnlineStats {
+                nan_policy: self.nan_policy,
+                nans: self.nans,
+                ..OnlineStats::default()
+            };
+            return;
+        }
+
+        // `self` is the merge of the remainder `a` (weight `s1`) and `v`
+        // (weight `s2`), and each moment of `a` depends on the lower ones.
+        let n = self.weight;
+        let (s1, s2) = (n - v.weight, v.weight);
+        let mean = n.mul_add(self.mean, -s2 * v.mean) / s1;
+        let delta = v.mean - mean;
+        let meandiffsq = delta * delta;
+        let q = (self.q - v.q - meandiffsq * s1 * s2 / n).max(0.0);
+        let m3 = self.m3
+            - v.m3
+            - delta * meandiffsq * s1 * s2 * (s1 - s2) / (n * n)
+            - 3.0 * delta * s1.mul_add(v.q, -s2 * q) / n;
+        self.m4 = self.m4
+            - v.m4
+            - meandiffsq * meandiffsq * s1 * s2 * s1.mul_add(s1, s2.mul_add(s2, -s1 * s2))
+                / (n * n * n)
+            - 6.0 * meandiffsq * s1.mul_add(s1 * v.q, s2 * s2 * q) / (n * n)
+            - 4.0 * delta * s1.mul_add(v.m3, -s2 * m3) / n;
+        self.m3 = m3;
+        self.q = q;
+        self.mean = mean;
+
+        // Some weight is left, so at least one data point is.
+        self.size = (self.size - v.size).max(1);
+        self.weight = s1;
+        self.weight2 -= v.weight2;
+    }
+}
+
 impl Default for OnlineStats {
     fn default() -> OnlineStats {
         OnlineStats {
             size: 0,
+            weight: 0.0,
+            weight2: 0.0,
+            nan_policy: NanPolicy::default(),
+            nans: 0,
             mean: 0.0,
             q: 0.0,
+            m3: 0.0,
+            m4: 0.0,
         }
     }
 }
@@ -164,6 +587,21 @@ impl<T: ToPrimitive> FromIterator<T> for OnlineStats {
     }
 }
 
+impl<T: ToPrimitive + Send> FromParallelIterator<T> for OnlineStats {
+    #[inline]
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(it: I) -> OnlineStats {
+        it.into_par_iter()
+            .fold(OnlineStats::new, |mut v, sample| {
+                v.add(&sample);
+                v
+            })
+            .reduce(OnlineStats::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+}
+
 impl<T: ToPrimitive> Extend<T> for OnlineStats {
     #[inline]
     fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
@@ -173,9 +611,545 @@ impl<T: ToPrimitive> Extend<T> for OnlineStats {
     }
 }
 
+/// Online state for computing the sum of a stream.
+///
+/// If every sample (and weight) has been an integer, the sum is computed
+/// exactly and then rounded once, e.g. like a database's `SUM()`. Otherwise,
+/// it uses Neumaier's compensated summation, which is exact unless the sum
+/// loses more than twice the precision of an `f64` to cancellation.
+///
+/// `NaN` samples make the sum `NaN`, unless the `NanPolicy` is `Skip`.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct SumStats {
+    size: u64,
+    /// The sum of the weights, which is `size` if all weights are `1`.
+    weight: f64,
+    /// The (weighted) sum of the samples, with `compensation` holding the
+    /// low-order bits lost to rounding.
+    sum: f64,
+    compensation: f64,
+    /// The exact sum, as long as every sample has been an integer and the
+    /// sum fits.
+    integer_sum: Option<i128>,
+    nan_policy: NanPolicy,
+}
+
+impl SumStats {
+    /// Create initial state.
+    #[must_use]
+    pub fn new() -> SumStats {
+        Default::default()
+    }
+
+    /// Create initial state that handles `NaN`s according to `nan_policy`.
+    #[must_use]
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> SumStats {
+        SumStats {
+            nan_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Initializes the sum from a sample.
+    #[must_use]
+    pub fn from_slice<T: ToPrimitive>(samples: &[T]) -> SumStats {
+        let mut stats = SumStats::new();
+        for sample in samples {
+            stats.add(sample);
+        }
+        stats
+    }
+
+    /// Initializes the sum from a sample, splitting the work across the
+    /// rayon thread pool.
+    #[must_use]
+    pub fn par_from_slice<T: ToPrimitive + Sync>(samples: &[T]) -> SumStats {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(SumStats::from_slice)
+            .reduce(SumStats::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+
+    /// Add a new sample.
+    #[inline]
+    pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        let x = sample.to_f64().unwrap();
+        if x.is_nan() && self.nan_policy == NanPolicy::Skip {
+            return;
+        }
+        // Once the exact sum is lost, there is no need to check for integers.
+        let integer = self.integer_sum.and_then(|_| exact_integer(sample));
+        self.size += 1;
+        self.weight += 1.0;
+        self.add_to_sum(x, integer);
+    }
+
+    /// Add a new sample with the given weight, which is the same as adding
+    /// it `weight` times, up to rounding.
+    ///
+    /// Samples with a weight of `0` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    #[inline]
+    pub fn add_weighted<T: ToPrimitive>(&mut self, sample: &T, weight: f64) {
+        assert!(
+            weight >= 0.0 && weight.is_finite(),
+            "weight must be finite and non-negative"
+        );
+        if weight == 0.0 {
+            return;
+        }
+        let x = sample.to_f64().unwrap();
+        if x.is_nan() && self.nan_policy == NanPolicy::Skip {
+            return;
+        }
+        let integer = self
+            .integer_sum
+            .and_then(|_| exact_integer(sample)?.checked_mul(exact_integer(&weight)?));
+        self.size += 1;
+        self.weight += weight;
+        self.add_to_sum(x * weight, integer);
+    }
+
+    /// Remove a sample that was previously added.
+    ///
+    /// The exact sum is not restored once a non-integer has been added,
+    /// unless every sample is removed.
+    #[inline]
+    pub fn remove<T: ToPrimitive>(&mut self, sample: &T) {
+        let mut point = SumStats::with_nan_policy(self.nan_policy);
+        point.add(sample);
+        self.subtract(&point);
+    }
+
+    /// Adds `x` to the sum, and `integer` to the exact sum if it is still
+    /// being tracked.
+    #[inline]
+    fn add_to_sum(&mut self, x: f64, integer: Option<i128>) {
+        // Taken from: Neumaier, A. (1974), "Rundungsfehleranalyse einiger
+        // Verfahren zur Summation endlicher Summen"
+        let t = self.sum + x;
+        if self.sum.abs() >= x.abs() {
+            self.compensation += (self.sum - t) + x;
+        } else {
+            self.compensation += (x - t) + self.sum;
+        }
+        self.sum = t;
+        self.integer_sum = self
+            .integer_sum
+            .zip(integer)
+            .and_then(|(sum, x)| sum.checked_add(x));
+    }
+
+    /// Return the current sum of the samples (weighted by their weights).
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        match self.integer_sum {
+            Some(sum) => sum as f64,
+            None => self.sum + self.compensation,
+        }
+    }
+
+    /// Return the exact sum of the samples, if every sample (and weight) has
+    /// been an integer and the sum fits in an `i128`.
+    #[must_use]
+    pub const fn integer_sum(&self) -> Option<i128> {
+        self.integer_sum
+    }
+
+    /// Returns the policy for `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Returns the sum of the weights of the data points.
+    #[inline]
+    #[must_use]
+    pub const fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns if empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl Commute for SumStats {
+    #[inline]
+    fn merge(&mut self, v: SumStats) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.nan_policy, v.nan_policy,
+            "cannot merge SumStats with different NaN policies"
+        );
+        self.size += v.size;
+        self.weight += v.weight;
+        self.add_to_sum(v.sum, v.integer_sum);
+        self.add_to_sum(v.compensation, Some(0));
+    }
+}
+
+impl Subtract for SumStats {
+    #[inline]
+    fn subtract(&mut self, v: &SumStats) {
+        if v.is_empty() {
+            return;
+        }
+        assert!(
+            v.size <= self.size,
+            "cannot subtract more data points than were added"
+        );
+        if v.weight >= self.weight {
+            *self = SumStats::with_nan_policy(self.nan_policy);
+            return;
+        }
+        // Some weight is left, so at least one data point is.
+        self.size = (self.size - v.size).max(1);
+        self.weight -= v.weight;
+        self.add_to_sum(-v.sum, v.integer_sum.map(|x| -x));
+        self.add_to_sum(-v.compensation, Some(0));
+    }
+}
+
+/// Returns `sample` as an integer, if it is one.
+#[inline]
+fn exact_integer<T: ToPrimitive>(sample: &T) -> Option<i128> {
+    // `to_i128` truncates floats, so check that nothing was lost.
+    let integer = sample.to_i128()?;
+    (integer as f64 == sample.to_f64()?).then_some(integer)
+}
+
+impl Default for SumStats {
+    fn default() -> SumStats {
+        SumStats {
+            size: 0,
+            weight: 0.0,
+            sum: 0.0,
+            compensation: 0.0,
+            integer_sum: Some(0),
+            nan_policy: NanPolicy::default(),
+        }
+    }
+}
+
+impl fmt::Debug for SumStats {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.sum())
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for SumStats {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> SumStats {
+        let mut v = SumStats::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for SumStats {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
+/// Online state for computing the geometric and harmonic means of a stream.
+///
+/// `NaN` samples make both means `NaN`, unless the `NanPolicy` is `Skip`.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MeanStats {
+    size: u64,
+    /// The sum of the weights, which is `size` if all weights are `1`.
+    weight: f64,
+    /// The (weighted) sum of the logs of the positive samples.
+    sum_ln: f64,
+    /// The (weighted) sum of the reciprocals of the non-zero samples.
+    sum_recip: f64,
+    /// The weight of the samples that are `0`, and that are negative.
+    zeros: f64,
+    negatives: f64,
+    nan_policy: NanPolicy,
+}
+
+impl MeanStats {
+    /// Create initial state.
+    #[must_use]
+    pub fn new() -> MeanStats {
+        Default::default()
+    }
+
+    /// Create initial state that handles `NaN`s according to `nan_policy`.
+    #[must_use]
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> MeanStats {
+        MeanStats {
+            nan_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Initializes the means from a sample.
+    #[must_use]
+    pub fn from_slice<T: ToPrimitive>(samples: &[T]) -> MeanStats {
+        let mut stats = MeanStats::new();
+        for sample in samples {
+            stats.add(sample);
+        }
+        stats
+    }
+
+    /// Initializes the means from a sample, splitting the work across the
+    /// rayon thread pool.
+    #[must_use]
+    pub fn par_from_slice<T: ToPrimitive + Sync>(samples: &[T]) -> MeanStats {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(MeanStats::from_slice)
+            .reduce(MeanStats::new, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+
+    /// Add a new sample.
+    #[inline]
+    pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        self.add_weighted(sample, 1.0);
+    }
+
+    /// Add a new sample with the given weight, which is the same as adding
+    /// it `weight` times.
+    ///
+    /// Samples with a weight of `0` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    #[inline]
+    pub fn add_weighted<T: ToPrimitive>(&mut self, sample: &T, weight: f64) {
+        assert!(
+            weight >= 0.0 && weight.is_finite(),
+            "weight must be finite and non-negative"
+        );
+        if weight == 0.0 {
+            return;
+        }
+        let x = sample.to_f64().unwrap();
+        if x.is_nan() && self.nan_policy == NanPolicy::Skip {
+            return;
+        }
+        self.size += 1;
+        self.weight += weight;
+        if x > 0.0 {
+            self.sum_ln += weight * x.ln();
+        } else if x == 0.0 {
+            self.zeros += weight;
+        } else if x < 0.0 {
+            self.negatives += weight;
+        } else {
+            self.sum_ln = f64::NAN;
+        }
+        if x != 0.0 {
+            self.sum_recip += weight / x;
+        }
+    }
+
+    /// Remove a sample that was previously added.
+    #[inline]
+    pub fn remove<T: ToPrimitive>(&mut self, sample: &T) {
+        let mut point = MeanStats::with_nan_policy(self.nan_policy);
+        point.add(sample);
+        self.subtract(&point);
+    }
+
+    /// Return the current geometric mean.
+    ///
+    /// This is `0` if any sample is `0`, and `NaN` if there are no samples
+    /// or any sample is negative.
+    #[must_use]
+    pub fn geometric_mean(&self) -> f64 {
+        if self.is_empty() || self.has_weight(self.negatives) {
+            f64::NAN
+        } else if self.has_weight(self.zeros) {
+            0.0
+        } else {
+            (self.sum_ln / self.weight).exp()
+        }
+    }
+
+    /// Return the current harmonic mean.
+    ///
+    /// This is `0` if any sample is `0`, and `NaN` if there are no samples
+    /// or any sample is negative.
+    #[must_use]
+    pub fn harmonic_mean(&self) -> f64 {
+        if self.is_empty() || self.has_weight(self.negatives) {
+            f64::NAN
+        } else if self.has_weight(self.zeros) {
+            0.0
+        } else {
+            self.weight / self.sum_recip
+        }
+    }
+
+    /// Returns true if `part` of the weight is more than the rounding errors
+    /// that removing weighted samples can leave behind.
+    #[inline]
+    fn has_weight(&self, part: f64) -> bool {
+        part > 1e-9 * self.weight
+    }
+
+    /// Returns the policy for `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Returns the sum of the weights of the data points.
+    #[inline]
+    #[must_use]
+    pub const fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.size as usize
+    }
+
+    /// Returns if empty.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl Commute for MeanStats {
+    #[inline]
+    fn merge(&mut self, v: MeanStats) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        assert_eq!(
+            self.nan_policy, v.nan_policy,
+            "cannot merge MeanStats with different NaN policies"
+        );
+        self.size += v.size;
+        self.weight += v.weight;
+        self.sum_ln += v.sum_ln;
+        self.sum_recip += v.sum_recip;
+        self.zeros += v.zeros;
+        self.negatives += v.negatives;
+    }
+}
+
+impl Subtract for MeanStats {
+    #[inline]
+    fn subtract(&mut self, v: &MeanStats) {
+        if v.is_empty() {
+            return;
+        }
+        assert!(
+            v.size <= self.size,
+            "cannot subtract more data points than were added"
+        );
+        if v.weight >= self.weight {
+            *self = MeanStats::with_nan_policy(self.nan_policy);
+            return;
+        }
+        // Some weight is left, so at least one data point is.
+        self.size = (self.size - v.size).max(1);
+        self.weight -= v.weight;
+        self.sum_ln -= v.sum_ln;
+        self.sum_recip -= v.sum_recip;
+        self.zeros -= v.zeros;
+        self.negatives -= v.negatives;
+    }
+}
+
+impl Default for MeanStats {
+    fn default() -> MeanStats {
+        MeanStats {
+            size: 0,
+            weight: 0.0,
+            sum_ln: 0.0,
+            sum_recip: 0.0,
+            zeros: 0.0,
+            negatives: 0.0,
+            nan_policy: NanPolicy::default(),
+        }
+    }
+}
+
+impl fmt::Debug for MeanStats {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "geometric {:.10}, harmonic {:.10}",
+            self.geometric_mean(),
+            self.harmonic_mean()
+        )
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for MeanStats {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> MeanStats {
+        let mut v = MeanStats::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for MeanStats {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::OnlineStats;
+    use super::{MeanStats, OnlineStats, SumStats};
+    use crate::{NanPolicy, Subtract};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
     use {crate::merge_all, crate::Commute};
 
     #[test]
@@ -192,6 +1166,241 @@ mod test {
         assert_eq!(expected.variance(), got.variance());
     }
 
+    #[test]
+    fn online_sample_population() {
+        let stats = OnlineStats::from_slice(&[2usize, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(stats.population_variance(), 4.0);
+        assert_eq!(stats.population_stddev(), 2.0);
+        assert_eq!(stats.variance(), stats.population_variance());
+        assert!((stats.sample_variance() - 32.0 / 7.0).abs() < 1e-12);
+        assert!((stats.sample_stddev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+
+        let single = OnlineStats::from_slice(&[1usize]);
+        assert!(single.sample_variance().is_nan());
+        assert_eq!(single.mean_confidence_interval(0.95), None);
+    }
+
+    #[test]
+    fn online_confidence_interval() {
+        // Matches R's `t.test(1:10)$conf.int`.
+        let stats = OnlineStats::from_slice(&[1usize, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert!((stats.standard_error() - 0.957_427_107_756_338_1).abs() < 1e-12);
+        let (lower, upper) = stats.mean_confidence_interval(0.95).unwrap();
+        assert!((lower - 3.334_149).abs() < 1e-6);
+        assert!((upper - 7.665_851).abs() < 1e-6);
+        assert_eq!(stats.mean_confidence_interval(1.0), None);
+    }
+
+    #[test]
+    fn online_skewness_kurtosis() {
+        let stats = OnlineStats::from_slice(&[2usize, 4, 4, 4, 5, 5, 7, 9]);
+        assert!((stats.skewness() - 0.656_250).abs() < 1e-12);
+        assert!((stats.kurtosis() - 2.781_250).abs() < 1e-12);
+        assert!((stats.excess_kurtosis() - -0.218_750).abs() < 1e-12);
+
+        let symmetric = OnlineStats::from_slice(&[1usize, 2, 3, 4, 5]);
+        assert!(symmetric.skewness().abs() < 1e-12);
+        assert!((symmetric.kurtosis() - 1.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn online_skewness_kurtosis_merge() {
+        let data = [
+            1.5f64, -2.0, 3.25, 8.0, 0.5, 13.0, -7.5, 2.0, 2.0, 41.0, 0.0,
+        ];
+        let expected = OnlineStats::from_slice(&data);
+
+        for split in 0..=data.len() {
+            let mut got = OnlineStats::from_slice(&data[..split]);
+            got.merge(OnlineStats::from_slice(&data[split..]));
+            assert_eq!(got.len(), expected.len());
+            assert!((got.mean() - expected.mean()).abs() < 1e-12);
+            assert!((got.variance() - expected.variance()).abs() < 1e-9);
+            assert!((got.skewness() - expected.skewness()).abs() < 1e-12);
+            assert!((got.kurtosis() - expected.kurtosis()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn online_weighted() {
+        // Frequency weights are the same as repeating the samples.
+        let mut weighted = OnlineStats::new();
+        for (x, w) in [(2, 1.0), (4, 3.0), (5, 2.0), (7, 1.0), (9, 1.0)] {
+            weighted.add_weighted(&x, w);
+        }
+        weighted.add_weighted(&100, 0.0);
+        let expanded = OnlineStats::from_slice(&[2usize, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!(weighted.len(), 5);
+        assert_eq!(weighted.weight(), 8.0);
+        for (got, expected) in [
+            (weighted.mean(), expanded.mean()),
+            (
+                weighted.population_variance(),
+                expanded.population_variance(),
+            ),
+            (weighted.sample_variance(), expanded.sample_variance()),
+            (weighted.skewness(), expanded.skewness()),
+            (weighted.kurtosis(), expanded.kurtosis()),
+        ] {
+            assert!((got - expected).abs() < 1e-12, "{got} != {expected}");
+        }
+
+        // Reliability weights do not depend on the scale of the weights.
+        let mut a = OnlineStats::new();
+        let mut b = OnlineStats::new();
+        for (x, w) in [(1.0, 0.5), (2.0, 1.0), (4.0, 0.25), (8.0, 2.0)] {
+            a.add_weighted(&x, w);
+            b.add_weighted(&x, w * 10.0);
+        }
+        assert!((a.reliability_variance() - b.reliability_variance()).abs() < 1e-12);
+        // With fractional weights summing to at most 1, only the reliability
+        // variance is defined.
+        let mut small = OnlineStats::new();
+        small.add_weighted(&1.0, 0.25);
+        small.add_weighted(&3.0, 0.5);
+        assert!(small.sample_variance().is_nan());
+        assert!(small.reliability_variance().is_finite());
+        assert!((a.reliability_variance() - 15.042_857_142_857_146).abs() < 1e-12);
+        assert_eq!(expanded.reliability_variance(), expanded.sample_variance());
+
+        // Mixing weighted and unweighted samples, and merging.
+        let mut mixed = OnlineStats::from_slice(&[2usize, 4]);
+        let mut rest = OnlineStats::new();
+        rest.add_weighted(&4, 2.0);
+        rest.add(&5);
+        rest.add_weighted(&5, 1.0);
+        rest.extend([7, 9]);
+        mixed.merge(rest);
+        assert!((mixed.sample_variance() - expanded.sample_variance()).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative")]
+    fn online_negative_weight() {
+        OnlineStats::new().add_weighted(&1, -1.0);
+    }
+
+    #[test]
+    fn online_cv() {
+        let stats = OnlineStats::from_slice(&[1usize, 2, 4, 8]);
+        assert!((stats.cv() - 7.1875f64.sqrt() / 3.75).abs() < 1e-12);
+        assert!(OnlineStats::from_slice(&[-1.0, 1.0]).cv().is_infinite());
+    }
+
+    #[test]
+    fn mean_stats() {
+        let stats = MeanStats::from_slice(&[1usize, 2, 4, 8]);
+        assert!((stats.geometric_mean() - 8f64.sqrt()).abs() < 1e-12);
+        assert!((stats.harmonic_mean() - 32.0 / 15.0).abs() < 1e-12);
+
+        // Weights and merging.
+        let mut weighted = MeanStats::from_slice(&[1usize, 2]);
+        weighted.add_weighted(&4, 2.0);
+        weighted.merge(MeanStats::from_slice(&[8usize]));
+        let expanded = MeanStats::from_slice(&[1usize, 2, 4, 4, 8]);
+        assert!((weighted.geometric_mean() - expanded.geometric_mean()).abs() < 1e-12);
+        assert!((weighted.harmonic_mean() - expanded.harmonic_mean()).abs() < 1e-12);
+        assert!((weighted.geometric_mean() - 2f64.powf(1.6)).abs() < 1e-12);
+
+        let zero = MeanStats::from_slice(&[3.0, 0.0, 5.0]);
+        assert_eq!(zero.geometric_mean(), 0.0);
+        assert_eq!(zero.harmonic_mean(), 0.0);
+        let mut negative = MeanStats::from_slice(&[3.0, 5.0]);
+        negative.merge(MeanStats::from_slice(&[-1.0]));
+        assert!(negative.geometric_mean().is_nan());
+        assert!(negative.harmonic_mean().is_nan());
+        assert!(MeanStats::new().geometric_mean().is_nan());
+
+        let data: Vec<u32> = (1..50_000).collect();
+        let parallel = MeanStats::par_from_slice(&data);
+        let sequential = MeanStats::from_slice(&data);
+        assert_eq!(parallel.len(), sequential.len());
+        assert!((parallel.geometric_mean() / sequential.geometric_mean() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mean_stats_remove_nan() {
+        let mut stats = MeanStats::from_slice(&[1.5, 2.0, 8.25, 3.0, 0.0]);
+        stats.remove(&0.0);
+        let expected = MeanStats::from_slice(&[1.5, 2.0, 8.25, 3.0]);
+        assert_eq!(stats.len(), 4);
+        assert!((stats.geometric_mean() - expected.geometric_mean()).abs() < 1e-12);
+        assert!((stats.harmonic_mean() - expected.harmonic_mean()).abs() < 1e-12);
+        stats.subtract(&expected);
+        assert_eq!(stats, MeanStats::new());
+
+        // Zeros and negatives are tracked by weight, so removing part of a
+        // weighted one leaves the rest.
+        let mut partial = MeanStats::from_slice(&[2.0, 8.0]);
+        partial.add_weighted(&0.0, 3.0);
+        partial.remove(&0.0);
+        partial.subtract(&MeanStats::from_slice(&[0.0]));
+        assert_eq!(partial.weight(), 3.0);
+        assert_eq!(partial.geometric_mean(), 0.0);
+        assert_eq!(partial.harmonic_mean(), 0.0);
+        partial.remove(&0.0);
+        assert!((partial.geometric_mean() - 4.0).abs() < 1e-12);
+        assert!((partial.harmonic_mean() - 3.2).abs() < 1e-12);
+
+        // Fractional weights that do not cancel exactly still count as gone.
+        let mut negative = MeanStats::new();
+        negative.add_weighted(&-1.0, 0.1);
+        negative.add_weighted(&-1.0, 0.2);
+        let mut rest = MeanStats::new();
+        rest.add_weighted(&-1.0, 0.3);
+        partial.merge(negative);
+        assert!(partial.geometric_mean().is_nan());
+        partial.subtract(&rest);
+        assert!((partial.geometric_mean() - 4.0).abs() < 1e-12);
+
+        let mut skip = MeanStats::with_nan_policy(NanPolicy::Skip);
+        skip.extend([1.0, f64::NAN, 3.0]);
+        assert_eq!(skip.len(), 2);
+        assert!((skip.geometric_mean() - 3f64.sqrt()).abs() < 1e-12);
+        assert!(MeanStats::from_slice(&[1.0, f64::NAN])
+            .geometric_mean()
+            .is_nan());
+
+        let json = serde_json::to_string(&skip).unwrap();
+        assert_eq!(serde_json::from_str::<MeanStats>(&json).unwrap(), skip);
+    }
+
+    #[test]
+    fn online_nan() {
+        let data = [1.0, f64::NAN, 3.0];
+        let stats = OnlineStats::from_slice(&data);
+        assert!(stats.mean().is_nan());
+        assert_eq!((stats.len(), stats.nan_count()), (3, 1));
+
+        let mut skip = OnlineStats::with_nan_policy(NanPolicy::Skip);
+        skip.extend(data);
+        skip.add_weighted(&f64::NAN, 2.0);
+        assert_eq!(skip.mean(), 2.0);
+        assert_eq!((skip.len(), skip.nan_count()), (2, 2));
+
+        // `NaN` counts survive merging, even from otherwise empty states.
+        let mut merged = OnlineStats::with_nan_policy(NanPolicy::Skip);
+        merged.add(&f64::NAN);
+        merged.merge(skip);
+        assert_eq!(merged.nan_policy(), NanPolicy::Skip);
+        assert_eq!((merged.len(), merged.nan_count()), (2, 3));
+        merged.merge(OnlineStats::with_nan_policy(NanPolicy::Skip));
+        assert_eq!(merged.nan_count(), 3);
+
+        // An empty state takes the policy of what is merged into it.
+        let mut empty = OnlineStats::new();
+        empty.merge(merged);
+        assert_eq!(empty.nan_policy(), NanPolicy::Skip);
+    }
+
+    #[test]
+    #[should_panic(expected = "different NaN policies")]
+    fn online_nan_policy_mismatch() {
+        let mut skip = OnlineStats::with_nan_policy(NanPolicy::Skip);
+        skip.add(&1.0);
+        skip.merge(OnlineStats::from_slice(&[2.0]));
+    }
+
     #[test]
     fn online_empty() {
         let expected = OnlineStats::new();
@@ -221,4 +1430,206 @@ mod test {
             merge_all(vars.into_iter()).unwrap().variance()
         );
     }
+
+    #[test]
+    fn online_parallel() {
+        let data: Vec<i64> = (0..100_000i64).map(|i| (i * 7_919) % 1_000 - 300).collect();
+        let sequential = OnlineStats::from_slice(&data);
+        let parallel = OnlineStats::par_from_slice(&data);
+        assert_eq!(parallel.len(), sequential.len());
+        assert!((parallel.mean() - sequential.mean()).abs() < 1e-9);
+        assert!((parallel.variance() - sequential.variance()).abs() < 1e-6);
+        assert!((parallel.kurtosis() - sequential.kurtosis()).abs() < 1e-9);
+
+        let collected: OnlineStats = data.into_par_iter().map(|x| x as f64).collect();
+        assert_eq!(collected.len(), sequential.len());
+        assert!((collected.mean() - sequential.mean()).abs() < 1e-9);
+
+        let empty = OnlineStats::par_from_slice::<f64>(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn sum_stats() {
+        // Cancellation that loses the small terms with naive or Kahan
+        // summation.
+        let stats = SumStats::from_slice(&[1.0, 1e100, 1.0, -1e100]);
+        assert_eq!(stats.integer_sum(), None);
+        assert_eq!(stats.sum(), 2.0);
+        let stats = SumStats::from_slice(&[0.1f64; 10]);
+        assert_eq!(stats.sum(), 1.0);
+
+        // Integers beyond the precision of an `f64` are summed exactly.
+        let big = (1u64 << 53) + 1;
+        let stats = SumStats::from_slice(&[big, big, 1]);
+        assert_eq!(stats.integer_sum(), Some(2 * i128::from(big) + 1));
+        let stats = SumStats::from_slice(&[i64::MAX, i64::MAX, -i64::MAX, -i64::MAX, 7]);
+        assert_eq!(stats.integer_sum(), Some(7));
+        assert_eq!(stats.sum(), 7.0);
+        // Integral floats count as integers, but fractional ones do not.
+        let stats = SumStats::from_slice(&[2.0, 3.0]);
+        assert_eq!(stats.integer_sum(), Some(5));
+        let stats = SumStats::from_slice(&[2.0, 3.5]);
+        assert_eq!(stats.integer_sum(), None);
+        assert_eq!(stats.sum(), 5.5);
+        let stats = SumStats::from_slice(&[u128::MAX]);
+        assert_eq!(stats.integer_sum(), None);
+
+        // Weighted samples and merging.
+        let mut weighted = SumStats::new();
+        weighted.add_weighted(&3, 4.0);
+        weighted.add_weighted(&1e100, 1.0);
+        assert_eq!(weighted.integer_sum(), None);
+        let mut more = SumStats::from_slice(&[-1e100, 0.5]);
+        more.add_weighted(&2, 0.25);
+        weighted.merge(more);
+        assert_eq!(weighted.sum(), 13.0);
+        let mut ints = SumStats::from_slice(&[1, 2]);
+        ints.add_weighted(&3, 2.0);
+        ints.merge(SumStats::from_slice(&[4]));
+        assert_eq!(ints.integer_sum(), Some(13));
+        assert_eq!(SumStats::new().sum(), 0.0);
+
+        let data: Vec<i64> = (0..100_000i64).map(|i| (i * 7_919) % 1_000 - 300).collect();
+        assert_eq!(
+            SumStats::par_from_slice(&data).integer_sum(),
+            SumStats::from_slice(&data).integer_sum()
+        );
+    }
+
+    #[test]
+    fn sum_stats_remove() {
+        let mut stats = SumStats::from_slice(&[1e100, 2.5, 3.0, -1e100, 4.0]);
+        stats.remove(&-1e100);
+        stats.remove(&1e100);
+        assert_eq!((stats.len(), stats.sum()), (3, 9.5));
+        assert_eq!(stats.integer_sum(), None);
+        stats.remove(&2.5);
+        stats.subtract(&SumStats::from_slice(&[3.0, 4.0]));
+        assert_eq!(stats, SumStats::new());
+
+        // Removing a sample with less weight than it was added with leaves
+        // the rest of its weight.
+        let mut partial = SumStats::new();
+        partial.add_weighted(&6, 2.5);
+        partial.remove(&6);
+        assert_eq!(
+            (partial.len(), partial.weight(), partial.sum()),
+            (1, 1.5, 9.0)
+        );
+    }
+
+    #[test]
+    fn sum_stats_nan() {
+        let mut skip = SumStats::with_nan_policy(NanPolicy::Skip);
+        skip.extend([1.0, f64::NAN, 2.0]);
+        assert_eq!((skip.len(), skip.integer_sum()), (2, Some(3)));
+        assert!(SumStats::from_slice(&[1.0, f64::NAN]).sum().is_nan());
+
+        let json = serde_json::to_string(&skip).unwrap();
+        assert_eq!(serde_json::from_str::<SumStats>(&json).unwrap(), skip);
+    }
+
+    #[test]
+    #[should_panic(expected = "different NaN policies")]
+    fn sum_stats_nan_policy_mismatch() {
+        let mut skip = SumStats::with_nan_policy(NanPolicy::Skip);
+        skip.add(&1.0);
+        skip.merge(SumStats::from_slice(&[2.0]));
+    }
+
+    #[test]
+    fn online_remove() {
+        let data: Vec<i64> = (0..200i64).map(|i| (i * 7_919) % 97 - 20).collect();
+        let mut stats = OnlineStats::from_slice(&data);
+        for i in (0..data.len() - 1).rev() {
+            stats.remove(&data[i + 1]);
+            let expected = OnlineStats::from_slice(&data[..=i]);
+            assert_eq!(stats.len(), expected.len());
+            assert!((stats.mean() - expected.mean()).abs() < 1e-9);
+            assert!((stats.variance() - expected.variance()).abs() < 1e-7);
+            if i > 10 {
+                assert!((stats.skewness() - expected.skewness()).abs() < 1e-6);
+                assert!((stats.kurtosis() - expected.kurtosis()).abs() < 1e-6);
+            }
+        }
+        stats.remove(&data[0]);
+        assert!(stats.is_empty());
+        assert_eq!(stats, OnlineStats::new());
+    }
+
+    #[test]
+    fn online_subtract() {
+        let (left, right) = ([1.5, 2.0, 8.25, 3.0], [4.0, 0.5, 6.0]);
+        let mut total = OnlineStats::from_slice(&left);
+        let part = OnlineStats::from_slice(&right);
+        total.merge(part);
+        total.subtract(&part);
+        let expected = OnlineStats::from_slice(&left);
+        assert_eq!(total.len(), 4);
+        assert!((total.mean() - expected.mean()).abs() < 1e-12);
+        assert!((total.variance() - expected.variance()).abs() < 1e-12);
+        assert!((total.m3 - expected.m3).abs() < 1e-9);
+        assert!((total.m4 - expected.m4).abs() < 1e-9);
+
+        let mut weighted = OnlineStats::new();
+        weighted.add_weighted(&3.0, 2.5);
+        weighted.add(&1.0);
+        weighted.remove(&1.0);
+        assert_eq!(weighted.weight(), 2.5);
+        assert!((weighted.mean() - 3.0).abs() < 1e-12);
+        assert!(weighted.variance().abs() < 1e-12);
+
+        // Removing a sample with less weight than it was added with leaves
+        // the rest of its weight, rather than resetting.
+        let mut partial = OnlineStats::new();
+        partial.add_weighted(&-2.0, 2.5);
+        partial.remove(&-2.0);
+        partial.remove(&-2.0);
+        assert_eq!((partial.len(), partial.weight()), (1, 0.5));
+        assert_eq!(partial.mean(), -2.0);
+
+        let mut skip = OnlineStats::with_nan_policy(NanPolicy::Skip);
+        skip.extend([1.0, f64::NAN, 2.0]);
+        skip.remove(&f64::NAN);
+        skip.remove(&2.0);
+        assert_eq!((skip.len(), skip.nan_count(), skip.mean()), (1, 0, 1.0));
+    }
+
+    #[test]
+    fn online_serde() {
+        let mut stats = OnlineStats::new();
+        stats.add_weighted(&2.0, 1.5);
+        stats.extend([1, 5]);
+        let bytes = bincode::serialize(&stats).unwrap();
+        assert_eq!(bincode::deserialize::<OnlineStats>(&bytes).unwrap(), stats);
+
+        let json = serde_json::to_string(&stats).unwrap();
+        let back: OnlineStats = serde_json::from_str(&json).unwrap();
+        assert_eq!((back.len(), back.weight()), (3, 3.5));
+        assert!((back.mean() - stats.mean()).abs() < 1e-12);
+        assert!((back.skewness() - stats.skewness()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn online_serde_baseline() {
+        // A snapshot of `[1, 2, 3]` from before any of the optional fields.
+        let json = r#"{"size":3,"mean":2.0,"q":2.0}"#;
+        let mut old: OnlineStats = serde_json::from_str(json).unwrap();
+        let exact = OnlineStats::from_slice(&[1, 2, 3]);
+        assert_eq!(old.len(), 3);
+        assert_eq!((old.weight(), old.mean()), (3.0, 2.0));
+        assert_eq!(old.variance(), exact.variance());
+        assert_eq!(old.sample_variance(), exact.sample_variance());
+        assert!(old.skewness().is_nan());
+
+        old.merge(OnlineStats::from_slice(&[4, 5]));
+        let exact = OnlineStats::from_slice(&[1, 2, 3, 4, 5]);
+        assert_eq!((old.len(), old.weight()), (5, 5.0));
+        assert!((old.mean() - exact.mean()).abs() < 1e-12);
+        assert!((old.variance() - exact.variance()).abs() < 1e-12);
+
+        let empty: OnlineStats = serde_json::from_str(r#"{"size":0,"mean":0.0,"q":0.0}"#).unwrap();
+        assert_eq!(empty, OnlineStats::new());
+    }
 }
@@ -0,0 +1,241 @@
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::OnlineStats;
+
+/// The scale factor that makes the MAD a consistent estimator of the
+/// standard deviation of a normal distribution, as used by Iglewicz and
+/// Hoaglin's modified z-score.
+const MODIFIED_Z_SCALE: f64 = 0.6745;
+
+/// A rule for deciding which data points are outliers.
+///
+/// Each rule has an `inner` and an `outer` threshold. Data points beyond the
+/// inner fences are mild outliers, and data points beyond the outer fences
+/// are extreme outliers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OutlierRule {
+    /// Tukey's fences at `Q1 - k * IQR` and `Q3 + k * IQR`, conventionally
+    /// with `k = 1.5` for the inner fences and `k = 3` for the outer fences.
+    Tukey { inner: f64, outer: f64 },
+    /// Data points whose z-score `(x - mean) / stddev` exceeds `k` in
+    /// absolute value.
+    ZScore { inner: f64, outer: f64 },
+    /// Data points whose modified z-score `0.6745 * (x - median) / MAD`
+    /// exceeds `k` in absolute value, from Iglewicz, B. and Hoaglin, D.
+    /// (1993), "How to Detect and Handle Outliers", who recommend `k = 3.5`.
+    ModifiedZScore { inner: f64, outer: f64 },
+}
+
+impl Default for OutlierRule {
+    fn default() -> OutlierRule {
+        OutlierRule::Tukey {
+            inner: 1.5,
+            outer: 3.0,
+        }
+    }
+}
+
+/// How far outside the fences a data point is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    /// Outside the inner fences, but not the outer fences.
+    Mild,
+    /// Outside the outer fences.
+    Extreme,
+}
+
+/// The inner and outer fences of an `OutlierRule`.
+///
+/// Data points on a fence are not outliers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fences {
+    /// Data points below this are extreme outliers.
+    pub lower_outer: f64,
+    /// Data points below this are (at least) mild outliers.
+    pub lower_inner: f64,
+    /// Data points above this are (at least) mild outliers.
+    pub upper_inner: f64,
+    /// Data points above this are extreme outliers.
+    pub upper_outer: f64,
+}
+
+impl Fences {
+    /// Tukey's fences for the given quartiles.
+    #[must_use]
+    pub fn tukey(q1: f64, q3: f64, inner: f64, outer: f64) -> Fences {
+        let iqr = q3 - q1;
+        Fences {
+            lower_outer: (-outer).mul_add(iqr, q1),
+            lower_inner: (-inner).mul_add(iqr, q1),
+            upper_inner: inner.mul_add(iqr, q3),
+            upper_outer: outer.mul_add(iqr, q3),
+        }
+    }
+
+    /// The z-score fences for the mean and (population) standard deviation
+    /// of `stats`.
+    #[must_use]
+    pub fn z_score(stats: &OnlineStats, inner: f64, outer: f64) -> Fences {
+        Fences::around(stats.mean(), stats.stddev(), inner, outer)
+    }
+
+    /// The modified z-score fences for the given median and median absolute
+    /// deviation (e.g. from `Unsorted::mad`).
+    ///
+    /// If `mad` is `0`, every data point other than the median is an extreme
+    /// outlier.
+    #[must_use]
+    pub fn modified_z_score(median: f64, mad: f64, inner: f64, outer: f64) -> Fences {
+        Fences::around(median, mad / MODIFIED_Z_SCALE, inner, outer)
+    }
+
+    fn around(center: f64, scale: f64, inner: f64, outer: f64) -> Fences {
+        Fences {
+            lower_outer: (-outer).mul_add(scale, center),
+            lower_inner: (-inner).mul_add(scale, center),
+            upper_inner: inner.mul_add(scale, center),
+            upper_outer: outer.mul_add(scale, center),
+        }
+    }
+
+    /// Returns the severity of `x` if it is an outlier, or `None` otherwise.
+    #[inline]
+    #[must_use]
+    pub fn classify(&self, x: f64) -> Option<Severity> {
+        if x < self.lower_outer || x > self.upper_outer {
+            Some(Severity::Extreme)
+        } else if x < self.lower_inner || x > self.upper_inner {
+            Some(Severity::Mild)
+        } else {
+            None
+        }
+    }
+}
+
+/// The outliers in a data set, and the fences used to find them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Outliers<T> {
+    /// The fences the outliers are outside of.
+    pub fences: Fences,
+    /// The data points (or their indices) outside the inner fences but not
+    /// the outer fences.
+    pub mild: Vec<T>,
+    /// The data points (or their indices) outside the outer fences.
+    pub extreme: Vec<T>,
+}
+
+impl<T> Outliers<T> {
+    /// Creates an empty set of outliers for `fences`.
+    #[must_use]
+    pub const fn new(fences: Fences) -> Outliers<T> {
+        Outliers {
+            fences,
+            mild: Vec::new(),
+            extreme: Vec::new(),
+        }
+    }
+
+    /// Adds `v` to the outliers if `x` is outside the fences.
+    #[inline]
+    pub fn add(&mut self, x: f64, v: T) {
+        match self.fences.classify(x) {
+            Some(Severity::Mild) => self.mild.push(v),
+            Some(Severity::Extreme) => self.extreme.push(v),
+            None => {}
+        }
+    }
+
+    /// Returns the number of outliers, mild or extreme.
+    #[inline]
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.mild.len() + self.extreme.len()
+    }
+
+    /// Returns true if there are no outliers.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+}
+
+/// Returns the indices of the data points in `data` that are outside
+/// `fences`, in ascending order.
+pub fn outlier_indices<T: ToPrimitive>(data: &[T], fences: Fences) -> Outliers<usize> {
+    let mut outliers = Outliers::new(fences);
+    for (i, x) in data.iter().enumerate() {
+        outliers.add(x.to_f64().unwrap(), i);
+    }
+    outliers
+}
+
+#[cfg(test)]
+mod test {
+    use super::{outlier_indices, Fences, OutlierRule, Severity};
+    use crate::{OnlineStats, Unsorted};
+
+    fn data() -> Vec<f64> {
+        vec![
+            -40.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 22.0, 50.0,
+        ]
+    }
+
+    #[test]
+    fn tukey() {
+        let mut unsorted: Unsorted<f64> = data().into_iter().collect();
+        // Tukey's hinges are 3 and 10.
+        let fences = unsorted.fences(OutlierRule::default()).unwrap();
+        assert_eq!(fences, Fences::tukey(3.0, 10.0, 1.5, 3.0));
+        assert_eq!((fences.lower_outer, fences.lower_inner), (-18.0, -7.5));
+        assert_eq!((fences.upper_inner, fences.upper_outer), (20.5, 31.0));
+        assert_eq!(fences.classify(20.5), None);
+        assert_eq!(fences.classify(22.0), Some(Severity::Mild));
+
+        let outliers = unsorted.outliers(OutlierRule::default()).unwrap();
+        assert_eq!(outliers.mild, vec![22.0]);
+        assert_eq!(outliers.extreme, vec![-40.0, 50.0]);
+        assert_eq!(outliers.count(), 3);
+
+        let indices = outlier_indices(&data(), fences);
+        assert_eq!(indices.mild, vec![12]);
+        assert_eq!(indices.extreme, vec![0, 13]);
+    }
+
+    #[test]
+    fn z_score() {
+        let rule = OutlierRule::ZScore {
+            inner: 1.5,
+            outer: 2.5,
+        };
+        let stats = OnlineStats::from_slice(&data());
+        let fences = Fences::z_score(&stats, 1.5, 2.5);
+        let mut unsorted: Unsorted<f64> = data().into_iter().collect();
+        assert_eq!(unsorted.fences(rule), Some(fences));
+        let outliers = outlier_indices(&data(), fences);
+        assert_eq!(outliers.mild, vec![13]);
+        assert_eq!(outliers.extreme, vec![0]);
+    }
+
+    #[test]
+    fn modified_z_score() {
+        let rule = OutlierRule::ModifiedZScore {
+            inner: 3.5,
+            outer: 7.0,
+        };
+        let mut unsorted: Unsorted<f64> = data().into_iter().collect();
+        // The median is 6.5 and the MAD is 3.5, so the inner fences are
+        // 6.5 -/+ 3.5 * 3.5 / 0.6745.
+        let fences = unsorted.fences(rule).unwrap();
+        assert_eq!(fences, Fences::modified_z_score(6.5, 3.5, 3.5, 7.0));
+        assert!((fences.upper_inner - 24.661_601_186_063_75).abs() < 1e-9);
+        let outliers = unsorted.outliers(rule).unwrap();
+        assert!(outliers.mild.is_empty());
+        assert_eq!(outliers.extreme, vec![-40.0, 50.0]);
+
+        let mut empty: Unsorted<f64> = Unsorted::new();
+        assert_eq!(empty.fences(rule), None);
+        assert!(empty.outliers(rule).is_none());
+    }
+}
@@ -0,0 +1,310 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+use crate::{Commute, Unsorted};
+
+/// The default number of samples to keep.
+const DEFAULT_CAPACITY: usize = 1_000;
+
+/// A commutative data structure for a uniform random sample of a stream.
+///
+/// This is reservoir sampling (Vitter's Algorithm R): after `n` data points,
+/// every subset of `min(n, capacity())` of them is equally likely to be the
+/// sample.
+///
+/// Merging two reservoirs draws the number of samples to keep from each side
+/// from the hypergeometric distribution given by the number of data points
+/// each side has seen, so a reservoir built from parallel chunks is a uniform
+/// sample of the whole stream.
+///
+/// Reservoirs created with `new` or `with_capacity` are seeded randomly. Use
+/// `with_seed` for reproducible samples.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct Reservoir<T> {
+    capacity: usize,
+    seen: u64,
+    sample: Vec<T>,
+    rng: Rng,
+}
+
+impl<T> Reservoir<T> {
+    /// Create an empty reservoir with the default capacity (`1000` samples).
+    #[must_use]
+    pub fn new() -> Reservoir<T> {
+        Default::default()
+    }
+
+    /// Create an empty reservoir that keeps at most `capacity` samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Reservoir<T> {
+        Reservoir::with_rng(capacity, Rng::from_entropy())
+    }
+
+    /// Create an empty reservoir that keeps at most `capacity` samples,
+    /// using a random number generator seeded with `seed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    #[must_use]
+    pub fn with_seed(capacity: usize, seed: u64) -> Reservoir<T> {
+        Reservoir::with_rng(capacity, Rng::with_seed(seed))
+    }
+
+    fn with_rng(capacity: usize, rng: Rng) -> Reservoir<T> {
+        assert!(capacity > 0, "Reservoir capacity must be positive");
+        Reservoir {
+            capacity,
+            seen: 0,
+            sample: Vec::new(),
+            rng,
+        }
+    }
+
+    /// Add a sample to the data.
+    #[inline]
+    pub fn add(&mut self, v: T) {
+        self.seen += 1;
+        if self.sample.len() < self.capacity {
+            self.sample.push(v);
+            return;
+        }
+        let i = self.rng.below(self.seen) as usize;
+        if i < self.capacity {
+            self.sample[i] = v;
+        }
+    }
+
+    /// Returns the sampled values, in no particular order.
+    #[inline]
+    #[must_use]
+    pub fn sample(&self) -> &[T] {
+        &self.sample
+    }
+
+    /// Consumes the reservoir and returns the sampled values.
+    #[inline]
+    #[must_use]
+    pub fn into_sample(self) -> Vec<T> {
+        self.sample
+    }
+
+    /// Returns the maximum number of samples kept.
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of data points seen, which may be larger than the
+    /// number of samples kept.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.seen as usize
+    }
+
+    /// Returns true if there are no data points.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.seen == 0
+    }
+
+    /// Keep a uniform random subset of `k` samples.
+    fn subsample(&mut self, k: usize) {
+        // A partial Fisher-Yates shuffle, which leaves the chosen samples at
+        // the front.
+        for i in 0..k {
+            let j = i + self.rng.below((self.sample.len() - i) as u64) as usize;
+            self.sample.swap(i, j);
+        }
+        self.sample.truncate(k);
+    }
+}
+
+impl<T: PartialOrd> Reservoir<T> {
+    /// Consumes the reservoir and returns its samples as an `Unsorted`, for
+    /// computing approximate order statistics in bounded memory.
+    #[inline]
+    #[must_use]
+    pub fn into_unsorted(self) -> Unsorted<T> {
+        self.sample.into_iter().collect()
+    }
+}
+
+impl<T> Commute for Reservoir<T> {
+    fn merge(&mut self, mut v: Reservoir<T>) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            self.capacity = v.capacity;
+            self.seen = v.seen;
+            self.sample = v.sample;
+            return;
+        }
+        let capacity = self.capacity.min(v.capacity);
+        let (mut left, mut right) = (self.seen, v.seen);
+        let size = (left + right).min(capacity as u64);
+
+        // Choose which side each of the merged samples comes from, without
+        // replacement, in proportion to the data points each side has seen.
+        let mut from_self = 0;
+        for _ in 0..size {
+            if self.rng.below(left + right) < left {
+                from_self += 1;
+                left -= 1;
+            } else {
+                right -= 1;
+            }
+        }
+        let from_other = (size - from_self) as usize;
+        let from_self = from_self as usize;
+
+        // Each side's sample is a uniform sample of its data, so a uniform
+        // subset of it is too.
+        self.subsample(from_self);
+        v.rng = Rng::with_seed(self.rng.next_u64());
+        v.subsample(from_other);
+        self.sample.append(&mut v.sample);
+        self.seen += v.seen;
+        self.capacity = capacity;
+    }
+}
+
+impl<T> Default for Reservoir<T> {
+    #[inline]
+    fn default() -> Reservoir<T> {
+        Reservoir::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Reservoir<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} (n={})", self.sample, self.seen)
+    }
+}
+
+impl<T> FromIterator<T> for Reservoir<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> Reservoir<T> {
+        let mut v = Reservoir::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T> Extend<T> for Reservoir<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Reservoir;
+    use crate::Commute;
+
+    #[test]
+    fn reservoir_small() {
+        let mut r = Reservoir::with_seed(10, 1);
+        r.extend(0..5);
+        let mut sample = r.clone().into_sample();
+        sample.sort_unstable();
+        assert_eq!(sample, vec![0, 1, 2, 3, 4]);
+        assert_eq!(r.len(), 5);
+
+        r.extend(5..1_000);
+        assert_eq!(r.len(), 1_000);
+        assert_eq!(r.sample().len(), 10);
+        assert_eq!(r.into_unsorted().len(), 10);
+    }
+
+    #[test]
+    fn reservoir_seeded() {
+        let a: Vec<u32> = {
+            let mut r = Reservoir::with_seed(20, 42);
+            r.extend(0..10_000);
+            r.into_sample()
+        };
+        let mut r = Reservoir::with_seed(20, 42);
+        r.extend(0..10_000);
+        assert_eq!(a, r.into_sample());
+    }
+
+    /// Checks that every value in `0..n` is sampled about equally often.
+    fn assert_uniform(n: usize, k: usize, trials: u64, sample: impl Fn(u64) -> Vec<usize>) {
+        let mut hits = vec![0u64; n];
+        for seed in 0..trials {
+            let s = sample(seed);
+            assert_eq!(s.len(), k);
+            for v in s {
+                hits[v] += 1;
+            }
+        }
+        let expected = (trials * k as u64) as f64 / n as f64;
+        // A binomial count is within 5 standard deviations of its mean.
+        let tolerance = 5.0 * expected.sqrt();
+        for (v, &h) in hits.iter().enumerate() {
+            assert!(
+                (h as f64 - expected).abs() < tolerance,
+                "{v} sampled {h} times, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn reservoir_uniform() {
+        assert_uniform(100, 10, 4_000, |seed| {
+            let mut r = Reservoir::with_seed(10, seed);
+            r.extend(0..100);
+            r.into_sample()
+        });
+    }
+
+    #[test]
+    fn reservoir_merge_uniform() {
+        // Unequal chunks, so a merge that ignored the number of data points
+        // each side has seen would oversample the small chunk.
+        assert_uniform(100, 10, 4_000, |seed| {
+            let mut a = Reservoir::with_seed(10, seed);
+            a.extend(0..10);
+            let mut b = Reservoir::with_seed(10, seed + 1_000_000);
+            b.extend(10..70);
+            let mut c = Reservoir::with_seed(20, seed + 2_000_000);
+            c.extend(70..100);
+            a.merge(b);
+            a.merge(c);
+            assert_eq!(a.len(), 100);
+            a.into_sample()
+        });
+    }
+
+    #[test]
+    fn reservoir_merge_empty() {
+        let mut a: Reservoir<u32> = Reservoir::default();
+        let b: Reservoir<u32> = (0..5).collect();
+        a.merge(b.clone());
+        a.merge(Reservoir::with_capacity(3));
+        assert_eq!(a.sample(), b.sample());
+        assert_eq!(a.capacity(), b.capacity());
+
+        let mut c = Reservoir::with_seed(3, 0);
+        c.extend(0..2);
+        c.merge((2..4).collect());
+        assert_eq!(c.len(), 4);
+        assert_eq!(c.capacity(), 3);
+        assert_eq!(c.sample().len(), 3);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// Taken from: https://prng.di.unimi.it/splitmix64.c
+///
+/// This is not cryptographically secure; it is only used for sampling.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) const fn with_seed(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Create a generator seeded from the process's random hasher keys.
+    pub(crate) fn from_entropy() -> Rng {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0x5eed);
+        Rng::with_seed(hasher.finish())
+    }
+
+    #[inline]
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed integer in `0..n`.
+    ///
+    /// Uses Lemire's multiply-shift method with rejection, so there is no
+    /// modulo bias.
+    #[inline]
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        debug_assert!(n > 0);
+        let threshold = n.wrapping_neg() % n;
+        loop {
+            let m = u128::from(self.next_u64()) * u128::from(n);
+            if (m as u64) >= threshold {
+                return (m >> 64) as u64;
+            }
+        }
+    }
+}
@@ -0,0 +1,786 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+
+use num_traits::ToPrimitive;
+use serde::de::{Deserializer, Error};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+use crate::{total_cmp, Partial};
+
+/// Compute statistics over a sliding window of the last `window` samples of
+/// a stream.
+///
+/// The returned iterator yields a `RollingSummary` after each sample.
+///
+/// # Panics
+///
+/// Panics if `window` is `0`.
+pub fn rolling<I>(it: I, window: usize) -> RollingIter<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: PartialOrd + ToPrimitive + Clone,
+{
+    RollingIter {
+        it: it.into_iter(),
+        rolling: Rolling::new(window),
+    }
+}
+
+/// A data structure for statistics over a sliding window of the last `N`
+/// samples.
+///
+/// Adding a sample evicts the oldest one once the window is full. Every
+/// statistic is maintained incrementally:
+///
+/// * The mean and variance use Welford's update in both directions, so
+///   adding and removing a sample takes `O(1)` time. Removals accumulate
+///   rounding errors, so these are recomputed from the window after every
+///   `N` removals, and sooner once samples much larger than the rest have
+///   left it, which keeps the time per sample amortized `O(1)`.
+/// * The minimum and maximum use monotonic deques, which take amortized
+///   `O(1)` time per sample.
+/// * Quantiles use an order-statistic treap, which takes expected
+///   `O(log N)` time per sample and per query.
+///
+/// Unlike the other accumulators in this crate, a window depends on the
+/// order of the samples, so it does not implement `Commute`.
+///
+/// Note that this works on types that do not define a total ordering like
+/// `f32` and `f64`. `NaN`s are ordered after every other value, and make the
+/// mean and variance `NaN` while they are in the window.
+///
+/// Only the samples in the window are serialized, and the rest is rebuilt
+/// from them when deserializing.
+#[derive(Clone)]
+pub struct Rolling<T> {
+    window: usize,
+    /// The number of samples ever added, used to expire deque entries.
+    seen: u64,
+    samples: VecDeque<T>,
+    moments: Moments,
+    /// Increasing candidates for the minimum, tagged by sample number.
+    mins: VecDeque<(u64, T)>,
+    /// Decreasing candidates for the maximum, tagged by sample number.
+    maxs: VecDeque<(u64, T)>,
+    order: Treap<T>,
+}
+
+/// The mean and variance of the finite samples in the window.
+#[derive(Clone, Default)]
+struct Moments {
+    size: u64,
+    mean: f64,
+    q: f64,
+    /// The number of `NaN`s and infinities in the window.
+    nonfinite: u64,
+    /// The number of removals since the moments were last recomputed.
+    removals: u64,
+    /// The largest square of a finite sample added since the moments were
+    /// last recomputed, which bounds the rounding errors of the removals.
+    peak: f64,
+}
+
+/// The statistics of a window after a step of `RollingIter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RollingSummary<T> {
+    /// The number of samples in the window.
+    pub len: usize,
+    /// The mean of the window.
+    pub mean: f64,
+    /// The population standard deviation of the window.
+    pub stddev: f64,
+    /// The minimum of the window.
+    pub min: T,
+    /// The maximum of the window.
+    pub max: T,
+    /// The median of the window.
+    pub median: f64,
+}
+
+impl<T: PartialOrd + ToPrimitive + Clone> Rolling<T> {
+    /// Create an empty window holding at most `window` samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is `0`.
+    #[must_use]
+    pub fn new(window: usize) -> Rolling<T> {
+        assert!(window > 0, "Rolling window must be positive");
+        Rolling {
+            window,
+            seen: 0,
+            samples: VecDeque::with_capacity(window),
+            moments: Moments::default(),
+            mins: VecDeque::new(),
+            maxs: VecDeque::new(),
+            order: Treap::new(),
+        }
+    }
+
+    /// Add a sample to the window, returning the evicted sample if the
+    /// window was full.
+    #[inline]
+    pub fn add(&mut self, sample: T) -> Option<T> {
+        let evicted = if self.samples.len() == self.window {
+            let old = self.samples.pop_front().unwrap();
+            self.moments.remove(&old);
+            self.order.remove(&old);
+            Some(old)
+        } else {
+            None
+        };
+
+        let index = self.seen;
+        self.seen += 1;
+        // Entries older than the window can no longer be the min or max.
+        let oldest = self.seen.saturating_sub(self.window as u64);
+        while self.mins.front().is_some_and(|&(i, _)| i < oldest) {
+            self.mins.pop_front();
+        }
+        while self.maxs.front().is_some_and(|&(i, _)| i < oldest) {
+            self.maxs.pop_front();
+        }
+        while self
+            .mins
+            .back()
+            .is_some_and(|(_, v)| total_cmp(v, &sample).is_gt())
+        {
+            self.mins.pop_back();
+        }
+        while self
+            .maxs
+            .back()
+            .is_some_and(|(_, v)| total_cmp(v, &sample).is_lt())
+        {
+            self.maxs.pop_back();
+        }
+        self.mins.push_back((index, sample.clone()));
+        self.maxs.push_back((index, sample.clone()));
+
+        self.moments.add(&sample);
+        self.order.insert(sample.clone());
+        self.samples.push_back(sample);
+        if self.moments.is_stale(self.window) {
+            let mut moments = Moments::default();
+            for x in &self.samples {
+                moments.add(x);
+            }
+            self.moments = moments;
+        }
+        evicted
+    }
+
+    /// Returns the mean of the window.
+    ///
+    /// If the window is empty, `NaN` is returned.
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        if self.moments.nonfinite > 0 {
+            return self.exact_moments().0;
+        }
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            self.moments.mean
+        }
+    }
+
+    /// Returns the population variance of the window.
+    ///
+    /// If the window is empty, `NaN` is returned.
+    #[must_use]
+    pub fn variance(&self) -> f64 {
+        if self.moments.nonfinite > 0 {
+            return self.exact_moments().1;
+        }
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            (self.moments.q / self.len() as f64).max(0.0)
+        }
+    }
+
+    /// Returns the Bessel-corrected sample variance of the window.
+    ///
+    /// If the window has fewer than two samples, `NaN` is returned.
+    #[must_use]
+    pub fn sample_variance(&self) -> f64 {
+        let n = self.len() as f64;
+        if n < 2.0 {
+            return f64::NAN;
+        }
+        self.variance() * n / (n - 1.0)
+    }
+
+    /// Returns the population standard deviation of the window.
+    #[must_use]
+    pub fn stddev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the minimum of the window.
+    #[inline]
+    #[must_use]
+    pub fn min(&self) -> Option<&T> {
+        self.mins.front().map(|(_, v)| v)
+    }
+
+    /// Returns the maximum of the window.
+    #[inline]
+    #[must_use]
+    pub fn max(&self) -> Option<&T> {
+        self.maxs.front().map(|(_, v)| v)
+    }
+
+    /// Returns the median of the window.
+    #[must_use]
+    pub fn median(&self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the `q`-th quantile (`0 <= q <= 1`) of the window, linearly
+    /// interpolated between the closest ranks as with
+    /// `QuantileMethod::Linear`.
+    ///
+    /// If the window is empty or `q` is out of range, `None` is returned.
+    #[must_use]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.is_empty() || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let h = q * (self.len() - 1) as f64;
+        let (lo, hi) = (h.floor() as usize, h.ceil() as usize);
+        let low = self.order.kth(lo)?.to_f64()?;
+        if lo == hi {
+            return Some(low);
+        }
+        let high = self.order.kth(hi)?.to_f64()?;
+        Some((h - lo as f64).mul_add(high - low, low))
+    }
+
+    /// Returns the `k`-th smallest sample (from `0`) in the window.
+    #[inline]
+    #[must_use]
+    pub fn select_nth(&self, k: usize) -> Option<&T> {
+        self.order.kth(k)
+    }
+
+    /// Returns the statistics of the window, or `None` if it is empty.
+    #[must_use]
+    pub fn summary(&self) -> Option<RollingSummary<T>> {
+        Some(RollingSummary {
+            len: self.len(),
+            mean: self.mean(),
+            stddev: self.stddev(),
+            min: self.min()?.clone(),
+            max: self.max()?.clone(),
+            median: self.median()?,
+        })
+    }
+
+    /// Returns the samples in the window, from oldest to newest.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.samples.iter()
+    }
+
+    /// Returns the maximum number of samples in the window.
+    #[inline]
+    #[must_use]
+    pub const fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Returns the number of samples in the window.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns true if there are no samples in the window.
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns true if the next sample will evict the oldest one.
+    #[inline]
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.samples.len() == self.window
+    }
+
+    /// The mean and population variance computed directly from the window,
+    /// which is only needed when it contains infinities or `NaN`s.
+    fn exact_moments(&self) -> (f64, f64) {
+        let n = self.len() as f64;
+        let mean = self
+            .samples
+            .iter()
+            .map(|x| x.to_f64().unwrap_or(f64::NAN))
+            .sum::<f64>()
+            / n;
+        let variance = self
+            .samples
+            .iter()
+            .map(|x| {
+                let d = x.to_f64().unwrap_or(f64::NAN) - mean;
+                d * d
+            })
+            .sum::<f64>()
+            / n;
+        (mean, variance)
+    }
+}
+
+impl Moments {
+    #[inline]
+    fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        let x = sample.to_f64().unwrap_or(f64::NAN);
+        if !x.is_finite() {
+            self.nonfinite += 1;
+            return;
+        }
+        self.size += 1;
+        self.peak = self.peak.max(x * x);
+        let delta = x - self.mean;
+        self.mean += delta / self.size as f64;
+        self.q = delta.mul_add(x - self.mean, self.q);
+    }
+
+    /// Reverses `add`.
+    #[inline]
+    fn remove<T: ToPrimitive>(&mut self, sample: &T) {
+        self.removals += 1;
+        let x = sample.to_f64().unwrap_or(f64::NAN);
+        if !x.is_finite() {
+            self.nonfinite -= 1;
+            return;
+        }
+        self.size -= 1;
+        if self.size == 0 {
+            self.mean = 0.0;
+            self.q = 0.0;
+            return;
+        }
+        let delta = x - self.mean;
+        self.mean -= delta / self.size as f64;
+        self.q = (-delta).mul_add(x - self.mean, self.q);
+    }
+
+    /// Returns true if the moments should be recomputed from the window.
+    ///
+    /// Each removal can be off by a few ulps of the largest squares seen,
+    /// which matters once there have been many removals, or once the sum of
+    /// the squares left in the window is much smaller than those squares.
+    #[inline]
+    fn is_stale(&self, window: usize) -> bool {
+        let sum_squares = (self.size as f64).mul_add(self.mean * self.mean, self.q);
+        self.removals >= window as u64 || self.peak > 1e4 * sum_squares
+    }
+}
+
+/// The serialized form of `Rolling`.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: Clone + Deserialize<'de>"))]
+struct RawRolling<'a, T: Clone> {
+    window: usize,
+    samples: Cow<'a, VecDeque<T>>,
+}
+
+impl<T: Serialize + Clone> Serialize for Rolling<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RawRolling {
+            window: self.window,
+            samples: Cow::Borrowed(&self.samples),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Rolling<T>
+where
+    T: PartialOrd + ToPrimitive + Clone + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawRolling::<T>::deserialize(deserializer)?;
+        let (window, samples) = (raw.window, raw.samples.into_owned());
+        if window == 0 || samples.len() > window {
+            return Err(D::Error::custom(format!(
+                "Rolling window must be positive and hold at most {window} samples, got {}",
+                samples.len()
+            )));
+        }
+        // Unlike `new`, this does not trust `window` to preallocate.
+        let mut rolling = Rolling {
+            window,
+            seen: 0,
+            samples: VecDeque::with_capacity(samples.len()),
+            moments: Moments::default(),
+            mins: VecDeque::new(),
+            maxs: VecDeque::new(),
+            order: Treap::new(),
+        };
+        rolling.extend(samples);
+        Ok(rolling)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Rolling<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Rolling({}/{}) ", self.samples.len(), self.window)?;
+        f.debug_list().entries(&self.samples).finish()
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive + Clone> Extend<T> for Rolling<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+/// An iterator that yields the statistics of a sliding window after each
+/// sample of the underlying iterator.
+///
+/// This is created by the `rolling` function.
+#[derive(Clone, Debug)]
+pub struct RollingIter<I: Iterator> {
+    it: I,
+    rolling: Rolling<I::Item>,
+}
+
+impl<I: Iterator> RollingIter<I> {
+    /// Returns the current window.
+    #[inline]
+    #[must_use]
+    pub const fn window(&self) -> &Rolling<I::Item> {
+        &self.rolling
+    }
+}
+
+impl<I> Iterator for RollingIter<I>
+where
+    I: Iterator,
+    I::Item: PartialOrd + ToPrimitive + Clone,
+{
+    type Item = RollingSummary<I::Item>;
+
+    #[inline]
+    fn next(&mut self) -> Option<RollingSummary<I::Item>> {
+        let sample = self.it.next()?;
+        self.rolling.add(sample);
+        self.rolling.summary()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.it.size_hint()
+    }
+}
+
+/// The index of a missing child in a `Treap`.
+const NIL: usize = usize::MAX;
+
+/// An order-statistic tree: a treap whose nodes know the size of their
+/// subtree, so the `k`-th smallest value can be found in `O(log n)`.
+///
+/// Nodes live in an arena, and freed slots are reused by later inserts.
+#[derive(Clone)]
+struct Treap<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    root: usize,
+    rng: Rng,
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    value: Partial<T>,
+    priority: u64,
+    left: usize,
+    right: usize,
+    size: usize,
+}
+
+impl<T: PartialOrd> Treap<T> {
+    fn new() -> Treap<T> {
+        Treap {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NIL,
+            rng: Rng::with_seed(0x7265_6170),
+        }
+    }
+
+    #[inline]
+    fn size(&self, n: usize) -> usize {
+        if n == NIL {
+            0
+        } else {
+            self.nodes[n].size
+        }
+    }
+
+    #[inline]
+    fn update(&mut self, n: usize) {
+        let (left, right) = (self.nodes[n].left, self.nodes[n].right);
+        self.nodes[n].size = 1 + self.size(left) + self.size(right);
+    }
+
+    fn insert(&mut self, value: T) {
+        let node = Node {
+            value: Partial(value),
+            priority: self.rng.next_u64(),
+            left: NIL,
+            right: NIL,
+            size: 1,
+        };
+        let n = if let Some(n) = self.free.pop() {
+            self.nodes[n] = node;
+            n
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        };
+        let (left, right) = self.split(self.root, n);
+        let left = self.join(left, n);
+        self.root = self.join(left, right);
+    }
+
+    /// Removes one occurrence of `value`, which must be in the tree.
+    fn remove(&mut self, value: &T) {
+        self.root = self.remove_from(self.root, value);
+    }
+
+    fn remove_from(&mut self, n: usize, value: &T) -> usize {
+        assert!(n != NIL, "removed a value that is not in the window");
+        match total_cmp(value, &self.nodes[n].value.0) {
+            Ordering::Less => {
+                let left = self.remove_from(self.nodes[n].left, value);
+                self.nodes[n].left = left;
+            }
+            Ordering::Greater => {
+                let right = self.remove_from(self.nodes[n].right, value);
+                self.nodes[n].right = right;
+            }
+            Ordering::Equal => {
+                self.free.push(n);
+                return self.join(self.nodes[n].left, self.nodes[n].right);
+            }
+        }
+        self.update(n);
+        n
+    }
+
+    /// Splits the subtree at `n` into the values less than node `pivot`'s
+    /// value and the rest.
+    fn split(&mut self, n: usize, pivot: usize) -> (usize, usize) {
+        if n == NIL {
+            return (NIL, NIL);
+        }
+        if self.nodes[n].value < self.nodes[pivot].value {
+            let (left, right) = self.split(self.nodes[n].right, pivot);
+            self.nodes[n].right = left;
+            self.update(n);
+            (n, right)
+        } else {
+            let (left, right) = self.split(self.nodes[n].left, pivot);
+            self.nodes[n].left = right;
+            self.update(n);
+            (left, n)
+        }
+    }
+
+    /// Joins two subtrees, where every value in `a` is at most every value
+    /// in `b`.
+    fn join(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        if self.nodes[a].priority > self.nodes[b].priority {
+            let right = self.join(self.nodes[a].right, b);
+            self.nodes[a].right = right;
+            self.update(a);
+            a
+        } else {
+            let left = self.join(a, self.nodes[b].left);
+            self.nodes[b].left = left;
+            self.update(b);
+            b
+        }
+    }
+
+    /// Returns the `k`-th smallest value (from `0`).
+    fn kth(&self, mut k: usize) -> Option<&T> {
+        let mut n = self.root;
+        while n != NIL {
+            let left = self.size(self.nodes[n].left);
+            match k.cmp(&left) {
+                Ordering::Less => n = self.nodes[n].left,
+                Ordering::Equal => return Some(&self.nodes[n].value.0),
+                Ordering::Greater => {
+                    k -= left + 1;
+                    n = self.nodes[n].right;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rolling, Rolling};
+    use crate::{OnlineStats, Unsorted};
+
+    fn data() -> Vec<i64> {
+        (0..300i64).map(|i| (i * 7_919) % 101 - 50).collect()
+    }
+
+    #[test]
+    fn rolling_brute_force() {
+        let data = data();
+        for window in [1, 2, 7, 50] {
+            let mut r = Rolling::new(window);
+            for (i, &x) in data.iter().enumerate() {
+                let evicted = r.add(x);
+                assert_eq!(evicted, i.checked_sub(window).map(|j| data[j]));
+                let slice = &data[(i + 1).saturating_sub(window)..=i];
+                let exact = OnlineStats::from_slice(slice);
+                let mut sorted: Unsorted<i64> = slice.iter().copied().collect();
+
+                assert_eq!(r.len(), slice.len());
+                assert!((r.mean() - exact.mean()).abs() < 1e-9);
+                assert!((r.variance() - exact.variance()).abs() < 1e-6);
+                assert_eq!(r.min(), slice.iter().min());
+                assert_eq!(r.max(), slice.iter().max());
+                assert_eq!(r.median(), sorted.median());
+                for k in 0..slice.len() {
+                    assert_eq!(r.select_nth(k), sorted.select_nth(k));
+                }
+            }
+            assert!(r.is_full());
+        }
+    }
+
+    #[test]
+    fn rolling_quantiles() {
+        let mut r = Rolling::new(4);
+        r.extend([10.0, 1.0, 4.0, 3.0, 2.0]);
+        assert_eq!(
+            r.iter().copied().collect::<Vec<_>>(),
+            vec![1.0, 4.0, 3.0, 2.0]
+        );
+        assert_eq!(r.quantile(0.0), Some(1.0));
+        assert_eq!(r.quantile(1.0), Some(4.0));
+        assert_eq!(r.quantile(0.25), Some(1.75));
+        assert_eq!(r.median(), Some(2.5));
+        assert_eq!(r.quantile(1.5), None);
+        assert_eq!(Rolling::<f64>::new(3).median(), None);
+    }
+
+    #[test]
+    fn rolling_nonfinite() {
+        let mut r = Rolling::new(3);
+        r.extend([1.0, f64::NAN, 3.0]);
+        assert!(r.mean().is_nan());
+        assert!(r.max().unwrap().is_nan());
+        assert_eq!(r.min(), Some(&1.0));
+        assert_eq!(r.median(), Some(3.0));
+
+        r.extend([f64::INFINITY]);
+        assert!(r.mean().is_nan());
+        r.extend([5.0]);
+        assert_eq!(r.mean(), f64::INFINITY);
+        r.extend([6.0, 7.0]);
+        assert_eq!(r.mean(), 6.0);
+        assert_eq!(r.variance(), 2.0 / 3.0);
+        assert_eq!(r.max(), Some(&7.0));
+    }
+
+    #[test]
+    fn rolling_large_magnitudes() {
+        let mut r = Rolling::new(3);
+        r.extend([1e12, 1e12 + 1.0, -1e12, 1.0, 2.0, 3.0]);
+        assert!((r.mean() - 2.0).abs() < 1e-12);
+        assert!((r.variance() - 2.0 / 3.0).abs() < 1e-12);
+
+        let mut r = Rolling::new(4);
+        r.extend((0..1_000_000).map(|i| 1e9 + f64::from(i % 1_000)));
+        for (i, x) in [0.1, 0.2, 0.3, 0.4].into_iter().enumerate() {
+            r.add(x);
+            let exact = OnlineStats::from_slice(&r.iter().copied().collect::<Vec<_>>());
+            assert!(
+                (r.mean() - exact.mean()).abs() <= 1e-9 * exact.mean().abs(),
+                "{i}"
+            );
+            assert!(
+                (r.variance() - exact.variance()).abs() <= 1e-9 * exact.variance(),
+                "{i}"
+            );
+        }
+        assert!((r.variance() - 0.0125).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rolling_iter() {
+        let steps: Vec<_> = rolling(vec![1u32, 5, 3, 8, 2], 3).collect();
+        assert_eq!(steps.len(), 5);
+        assert_eq!((steps[0].len, steps[0].median), (1, 1.0));
+        assert_eq!((steps[2].min, steps[2].max, steps[2].mean), (1, 5, 3.0));
+        assert_eq!((steps[4].min, steps[4].max, steps[4].median), (2, 8, 3.0));
+    }
+
+    #[test]
+    fn rolling_serde() {
+        let mut r = Rolling::new(3);
+        r.extend([4u32, 1, 9, 7]);
+        let json = serde_json::to_string(&r).unwrap();
+        let mut back: Rolling<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.median(), Some(7.0));
+        back.add(2);
+        r.add(2);
+        assert_eq!((back.min(), back.median()), (r.min(), r.median()));
+        assert_eq!(
+            back.iter().collect::<Vec<_>>(),
+            r.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            serde_json::to_string(&back).unwrap(),
+            r#"{"window":3,"samples":[9,7,2]}"#
+        );
+
+        for json in [
+            r#"{"window":0,"samples":[]}"#,
+            r#"{"window":2,"samples":[1,2,3]}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<Rolling<u32>>(json).is_err(),
+                "{json}"
+            );
+        }
+        // A large window is not preallocated.
+        let json = r#"{"window":1000000000000000000,"samples":[5]}"#;
+        let huge: Rolling<u32> = serde_json::from_str(json).unwrap();
+        assert_eq!((huge.len(), huge.mean()), (1, 5.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn rolling_empty_window() {
+        let _ = Rolling::<f64>::new(0);
+    }
+}
@@ -1,94 +1,67 @@
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
-// use ahash::AHashMap;
-use std::default::Default;
-use std::iter::{FromIterator, IntoIterator};
+use std::fmt;
 
 use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
 
 use {crate::Commute, crate::Partial};
 
-pub fn median_on_sorted<T>(data: &[T]) -> Option<f64>
-        where T: PartialOrd + ToPrimitive {
-    Some(match data.len() {
-        0 => return None,
-        1 =>  data.get(0)?.to_f64().unwrap(),
-        len if len % 2 == 0 => {
-            let idx = len / 2;
-            let v1 = data.get((idx) - 1)?.to_f64().unwrap();
-            let v2 = data.get(idx)?.to_f64().unwrap();
-            (v1 + v2) / 2.0
-        }
-        len => 
-            data.get(len / 2).to_f64()?.unwrap()
-        
-    })
+/// A commutative data structure for a running median.
+///
+/// The data is split across two heaps: a max-heap holding the lower half and
+/// a min-heap holding the upper half, with the lower half holding the extra
+/// element when the number of data points is odd. Adding a sample takes
+/// `O(log n)` time, and the median is always available in `O(1)` time.
+///
+/// Note that this works on types that do not define a total ordering like
+/// `f32` and `f64`. `NaN`s are ordered after every other value.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(
+    bound(deserialize = "T: PartialOrd + Deserialize<'de>"),
+    from = "RawSorted<T>"
+)]
+pub struct Sorted<T> {
+    lower: BinaryHeap<Partial<T>>,
+    upper: BinaryHeap<Reverse<Partial<T>>>,
 }
 
-pub fn mode_on_sorted<T, I>(it: I) -> Option<T>
-        where T: PartialOrd, I: Iterator<Item=T> {
-    // This approach to computing the mode works very nicely when the
-    // number of samples is large and is close to its cardinality.
-    // In other cases, a hashmap would be much better.
-    // But really, how can we know this when given an arbitrary stream?
-    // Might just switch to a hashmap to track frequencies. That would also
-    // be generally useful for discovering the cardinality of a sample.
-    let (mut mode, mut next) = (None, None);
-    let (mut mode_count, mut next_count) = (0usize, 0usize);
-    for x in it {
-        if mode.as_ref().map(|y| y == &x).unwrap_or(false) {
-            mode_count += 1;
-        } else if next.as_ref().map(|y| y == &x).unwrap_or(false) {
-            next_count += 1;
-        } else {
-            next = Some(x);
-            next_count = 0;
-        }
+/// The serialized form of `Sorted`, whose halves may not be split at the
+/// median.
+#[derive(Deserialize)]
+#[serde(bound(deserialize = "T: PartialOrd + Deserialize<'de>"))]
+struct RawSorted<T> {
+    lower: BinaryHeap<Partial<T>>,
+    upper: BinaryHeap<Reverse<Partial<T>>>,
+}
 
-        if next_count > mode_count {
-            mode = next;
-            mode_count = next_count;
-            next = None;
-            next_count = 0;
-        } else if next_count == mode_count {
-            mode = None;
-            mode_count = 0usize;
+impl<T: PartialOrd> From<RawSorted<T>> for Sorted<T> {
+    fn from(raw: RawSorted<T>) -> Sorted<T> {
+        let mut sorted = Sorted {
+            lower: raw.lower,
+            upper: raw.upper,
+        };
+        // If some element of the lower half is above one of the upper half,
+        // the halves are rebuilt from scratch.
+        if let (Some(max), Some(Reverse(min))) = (sorted.lower.peek(), sorted.upper.peek()) {
+            if max > min {
+                let (lower, upper) = (sorted.lower.into_vec(), sorted.upper.into_vec());
+                return lower
+                    .into_iter()
+                    .chain(upper.into_iter().map(|r| r.0))
+                    .map(|v| v.0)
+                    .collect();
+            }
         }
+        sorted.rebalance();
+        sorted
     }
-    mode
-}
-
-// pub fn mode_hashmap<T, I>(it: I) -> Option<T>
-// where
-//     T: Eq + Hash + Clone,
-//     I: Iterator<Item = T>,
-// {
-//     let mut counts = AHashMap::new();
-//     let mut mode = None;
-//     let mut mode_count = 0;
-//     for x in it {
-//         let count = counts.entry(x.clone()).or_insert(0);
-//         *count += 1;
-//         if *count > mode_count {
-//             mode = Some(x.clone());
-//             mode_count = *count;
-//         }
-//     }
-//     mode
-// }
-
-/// A commutative data structure for sorted sequences of data.
-///
-/// Note that this works on types that do not define a total ordering like
-/// `f32` and `f64`. Then an ordering is not defined, an arbitrary order
-/// is returned.
-#[derive(Clone, Deserialize, Serialize, Eq, PartialEq, Debug)]
-pub struct Sorted<T> {
-    data: BinaryHeap<Partial<T>>,
 }
 
 impl<T: PartialOrd> Sorted<T> {
     /// Create initial empty state.
     #[inline]
+    #[must_use]
     pub fn new() -> Sorted<T> {
         Default::default()
     }
@@ -96,60 +69,125 @@ impl<T: PartialOrd> Sorted<T> {
     /// Add a new element to the set.
     #[inline]
     pub fn add(&mut self, v: T) {
-        self.data.push(Partial(v))
+        let v = Partial(v);
+        if self.lower.peek().map_or(true, |max| v <= *max) {
+            self.lower.push(v);
+        } else {
+            self.upper.push(Reverse(v));
+        }
+        self.rebalance();
+    }
+
+    /// Returns the lower of the two middle elements, or the middle element
+    /// if the number of data points is odd.
+    #[inline]
+    #[must_use]
+    pub fn lower_median(&self) -> Option<&T> {
+        self.lower.peek().map(|v| &v.0)
+    }
+
+    /// Returns the upper of the two middle elements, or the middle element
+    /// if the number of data points is odd.
+    #[inline]
+    #[must_use]
+    pub fn upper_median(&self) -> Option<&T> {
+        if self.lower.len() > self.upper.len() {
+            self.lower_median()
+        } else {
+            self.upper.peek().map(|v| &v.0 .0)
+        }
     }
 
     /// Returns the number of data points.
     #[inline]
+    #[must_use]
     pub fn len(&self) -> usize {
-        self.data.len()
+        self.lower.len() + self.upper.len()
     }
-}
 
-impl<T: PartialOrd + Clone> Sorted<T> {
-    /// Returns the mode of the data.
+    /// Returns true if there are no data points.
     #[inline]
-    pub fn mode(&self) -> Option<T> {
-        let p = mode_on_sorted(self.data.clone().into_sorted_vec().into_iter());
-        p.map(|p| p.0)
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    // #[inline]
-    // pub fn mode2(&self) -> Option<T> {
-    //     let p = mode_hashmap(self.data.clone().into_iter());
-    //     p.map(|p| p.0)
-    // }
+    /// Moves the top of one heap to the other until the lower half has
+    /// either as many elements as the upper half or one more.
+    #[inline]
+    fn rebalance(&mut self) {
+        while self.lower.len() > self.upper.len() + 1 {
+            let v = self.lower.pop().unwrap();
+            self.upper.push(Reverse(v));
+        }
+        while self.upper.len() > self.lower.len() {
+            let Reverse(v) = self.upper.pop().unwrap();
+            self.lower.push(v);
+        }
+    }
 }
 
-impl<T: PartialOrd + ToPrimitive + Clone> Sorted<T> {
+impl<T: PartialOrd + ToPrimitive> Sorted<T> {
     /// Returns the median of the data.
+    ///
+    /// This is the mean of the two middle elements when the number of data
+    /// points is even.
     #[inline]
+    #[must_use]
     pub fn median(&self) -> Option<f64> {
-        // Grr. The only way to avoid the alloc here is to take `self` by
-        // value. Could return `(f64, Sorted<T>)`, but that seems a bit weird.
-        //
-        // NOTE: Can `std::mem::swap` help us here?
-        let data = self.data.clone().into_sorted_vec();
-        median_on_sorted(&*data)
+        let lower = self.lower_median()?.to_f64()?;
+        if self.lower.len() > self.upper.len() {
+            return Some(lower);
+        }
+        let upper = self.upper_median()?.to_f64()?;
+        Some((lower + upper) / 2.0)
     }
 }
 
 impl<T: PartialOrd> Commute for Sorted<T> {
     #[inline]
     fn merge(&mut self, v: Sorted<T>) {
-        // should this be `into_sorted_vec`?
-        self.data.extend(v.data.into_vec().into_iter());
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        // Each of `v`'s elements goes to the half it belongs in, which keeps
+        // every element of `lower` at most every element of `upper`.
+        let (lower, upper) = (v.lower.into_vec(), v.upper.into_vec());
+        for Partial(x) in lower.into_iter().chain(upper.into_iter().map(|r| r.0)) {
+            self.add(x);
+        }
     }
 }
 
 impl<T: PartialOrd> Default for Sorted<T> {
     #[inline]
-    fn default() -> Sorted<T> { Sorted { data: BinaryHeap::new() } }
+    fn default() -> Sorted<T> {
+        Sorted {
+            lower: BinaryHeap::new(),
+            upper: BinaryHeap::new(),
+        }
+    }
+}
+
+impl<T: PartialOrd + fmt::Debug> fmt::Debug for Sorted<T> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.lower_median(), self.upper_median()) {
+            (Some(lower), Some(upper)) => {
+                write!(f, "median [{lower:?}, {upper:?}] of {}", self.len())
+            }
+            _ => write!(f, "N/A"),
+        }
+    }
 }
 
 impl<T: PartialOrd> FromIterator<T> for Sorted<T> {
     #[inline]
-    fn from_iter<I: IntoIterator<Item=T>>(it: I) -> Sorted<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> Sorted<T> {
         let mut v = Sorted::new();
         v.extend(it);
         v
@@ -158,76 +196,104 @@ impl<T: PartialOrd> FromIterator<T> for Sorted<T> {
 
 impl<T: PartialOrd> Extend<T> for Sorted<T> {
     #[inline]
-    fn extend<I: IntoIterator<Item=T>>(&mut self, it: I) {
-        self.data.extend(it.into_iter().map(Partial))
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use num::ToPrimitive;
     use super::Sorted;
+    use crate::{merge_all, Commute, Unsorted};
 
-    fn median<T, I>(it: I) -> Option<f64>
-       where T: PartialOrd + ToPrimitive + Clone, I: Iterator<Item=T> {
-        it.collect::<Sorted<T>>().median()
+    fn median<T: PartialOrd + num_traits::ToPrimitive>(data: Vec<T>) -> Option<f64> {
+        data.into_iter().collect::<Sorted<T>>().median()
     }
 
-    fn mode<T, I>(it: I) -> Option<T>
-       where T: PartialOrd + Clone, I: Iterator<Item=T> {
-        it.collect::<Sorted<T>>().mode()
+    #[test]
+    fn median_stream() {
+        assert_eq!(median(vec![3usize, 5, 7, 9]), Some(6.0));
+        assert_eq!(median(vec![3usize, 5, 7]), Some(5.0));
+        assert_eq!(median(vec![9usize, 7, 5, 3]), Some(6.0));
+        assert_eq!(median(Vec::<usize>::new()), None);
     }
 
-    fn mode2<T, I>(it: I) -> Option<T>
-    where T: PartialOrd + Clone, I: Iterator<Item=T> {
-     it.collect::<Sorted<T>>().mode2()
- }
+    #[test]
+    fn median_floats() {
+        assert_eq!(median(vec![3.0f64, 5.0, 7.0, 9.0]), Some(6.0));
+        assert_eq!(median(vec![3.0f64, 5.0, 7.0]), Some(5.0));
+        assert_eq!(median(vec![3.0f64, f64::NAN, 1.0]), Some(3.0));
+    }
 
     #[test]
-    fn median_stream() {
-        assert_eq!(median(vec![3usize, 5, 7, 9].into_iter()), Some(6.0));
-        assert_eq!(median(vec![3usize, 5, 7].into_iter()), Some(5.0));
+    fn running_median() {
+        let data: Vec<i64> = (0..500i64).map(|i| (i * 7_919) % 1_009 - 500).collect();
+        let mut sorted = Sorted::new();
+        let mut unsorted = Unsorted::new();
+        for &x in &data {
+            sorted.add(x);
+            unsorted.add(x);
+            assert_eq!(sorted.median(), unsorted.median());
+        }
+        assert_eq!(sorted.len(), data.len());
     }
 
     #[test]
-    fn mode_stream() {
-        assert_eq!(mode(vec![3usize, 5, 7, 9].into_iter()), None);
-        assert_eq!(mode(vec![3usize, 3, 3, 3].into_iter()), Some(3));
-        assert_eq!(mode(vec![3usize, 3, 3, 4].into_iter()), Some(3));
-        assert_eq!(mode(vec![4usize, 3, 3, 3].into_iter()), Some(3));
-        assert_eq!(mode(vec![1usize, 1, 2, 3, 3].into_iter()), None);
-    }
-
-    // #[test]
-    // fn mode2_stream() {
-    //     assert_eq!(mode2(vec![3usize, 5, 7, 9].into_iter()), None);
-    //     assert_eq!(mode2(vec![3usize, 3, 3, 3].into_iter()), Some(3));
-    //     assert_eq!(mode2(vec![3usize, 3, 3, 4].into_iter()), Some(3));
-    //     assert_eq!(mode2(vec![4usize, 3, 3, 3].into_iter()), Some(3));
-    //     assert_eq!(mode2(vec![1usize, 1, 2, 3, 3].into_iter()), None);
-    // }
+    fn middle_elements() {
+        let mut sorted: Sorted<&str> = vec!["b", "d", "a"].into_iter().collect();
+        assert_eq!(sorted.lower_median(), Some(&"b"));
+        assert_eq!(sorted.upper_median(), Some(&"b"));
+        sorted.add("c");
+        assert_eq!(sorted.lower_median(), Some(&"b"));
+        assert_eq!(sorted.upper_median(), Some(&"c"));
+    }
 
     #[test]
-    fn median_floats() {
-        assert_eq!(median(vec![3.0f64, 5.0, 7.0, 9.0].into_iter()), Some(6.0));
-        assert_eq!(median(vec![3.0f64, 5.0, 7.0].into_iter()), Some(5.0));
+    fn merge() {
+        let data: Vec<u32> = (0..301u32).map(|i| (i * 613) % 307).collect();
+        let single: Sorted<u32> = data.iter().copied().collect();
+        let mut merged = merge_all(
+            data.chunks(40)
+                .map(|c| c.iter().copied().collect::<Sorted<u32>>()),
+        )
+        .unwrap();
+        assert_eq!(merged.len(), single.len());
+        assert_eq!(merged.median(), single.median());
+
+        merged.merge(Sorted::default());
+        assert_eq!(merged.median(), single.median());
+        let mut empty = Sorted::new();
+        empty.merge(merged);
+        assert_eq!(empty.median(), single.median());
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let sorted: Sorted<f64> = vec![4.0, 1.0, 3.0, 2.0].into_iter().collect();
+        let json = serde_json::to_string(&sorted).unwrap();
+        let mut back: Sorted<f64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.median(), Some(2.5));
+        back.add(10.0);
+        assert_eq!(back.median(), Some(3.0));
     }
 
     #[test]
-    fn mode_floats() {
-        assert_eq!(mode(vec![3.0f64, 5.0, 7.0, 9.0].into_iter()), None);
-        assert_eq!(mode(vec![3.0f64, 3.0, 3.0, 3.0].into_iter()), Some(3.0));
-        assert_eq!(mode(vec![3.0f64, 3.0, 3.0, 4.0].into_iter()), Some(3.0));
-        assert_eq!(mode(vec![4.0f64, 3.0, 3.0, 3.0].into_iter()), Some(3.0));
-        assert_eq!(mode(vec![1.0f64, 1.0, 2.0, 3.0, 3.0].into_iter()), None);
-    }
-
-    // #[test]
-    // fn mode2_floats() {
-    //     assert_eq!(mode2(vec![3.0f64, 5.0, 7.0, 9.0].into_iter()), None);
-    //     assert_eq!(mode2(vec![3.0f64, 3.0, 3.0, 3.0].into_iter()), Some(3.0));
-    //     assert_eq!(mode2(vec![3.0f64, 3.0, 3.0, 4.0].into_iter()), Some(3.0));
-    //     assert_eq!(mode2(vec![4.0f64, 3.0, 3.0, 3.0].into_iter()), Some(3.0));
-    //     assert_eq!(mode2(vec![1.0f64, 1.0, 2.0, 3.0, 3.0].into_iter()), None);
-    // }
+    fn serde_unbalanced() {
+        // The halves are rebalanced, or rebuilt if they overlap.
+        let json = r#"{"lower":[],"upper":[1,2,3]}"#;
+        let mut unbalanced: Sorted<u32> = serde_json::from_str(json).unwrap();
+        assert!(!unbalanced.is_empty());
+        assert_eq!((unbalanced.len(), unbalanced.median()), (3, Some(2.0)));
+        let json = r#"{"lower":[5,6],"upper":[1,2]}"#;
+        let overlapping: Sorted<u32> = serde_json::from_str(json).unwrap();
+        assert_eq!(overlapping.median(), Some(3.5));
+
+        unbalanced.merge(overlapping);
+        assert_eq!((unbalanced.len(), unbalanced.median()), (7, Some(2.0)));
+        let mut empty = Sorted::new();
+        empty.merge(unbalanced);
+        assert_eq!(empty.len(), 7);
+    }
 }
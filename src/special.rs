@@ -0,0 +1,162 @@
+// Special functions used internally by estimators that need a distribution's
+// CDF or quantile function (e.g. confidence intervals).
+
+use std::f64::consts::PI;
+
+/// Lanczos approximation coefficients (g = 7, n = 9).
+const LANCZOS: [f64; 9] = [
+    0.999_999_999_999_809_9,
+    676.520_368_121_885_1,
+    -1_259.139_216_722_402_8,
+    771.323_428_777_653_1,
+    -176.615_029_162_140_6,
+    12.507_343_278_686_905,
+    -0.138_571_095_265_720_12,
+    9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7,
+];
+
+/// Natural logarithm of the gamma function for `x > 0`.
+pub(crate) fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // Reflection formula.
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut a = LANCZOS[0];
+    let t = x + 7.5;
+    for (i, c) in LANCZOS.iter().enumerate().skip(1) {
+        a += c / (x + i as f64);
+    }
+    0.5f64.mul_add((2.0 * PI).ln(), (x + 0.5) * t.ln()) - t + a.ln()
+}
+
+/// Continued fraction for the regularized incomplete beta function.
+///
+/// Taken from: Numerical Recipes, 3rd ed., section 6.4 (modified Lentz).
+fn beta_cf(a: f64, b: f64, x: f64) -> f64 {
+    const MAX_ITER: usize = 300;
+    const EPS: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..=MAX_ITER {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = aa.mul_add(d, 1.0);
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = aa.mul_add(d, 1.0);
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+/// The regularized incomplete beta function `I_x(a, b)`.
+pub(crate) fn beta_inc(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_front =
+        ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a.mul_add(x.ln(), b * (1.0 - x).ln());
+    let front = ln_front.exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_cf(a, b, x) / a
+    } else {
+        1.0 - front * beta_cf(b, a, 1.0 - x) / b
+    }
+}
+
+/// The quantile function (inverse CDF) of Student's t-distribution with
+/// `df` degrees of freedom.
+///
+/// Returns `NaN` if `p` is not in `(0, 1)` or `df` is not positive.
+pub(crate) fn student_t_quantile(p: f64, df: f64) -> f64 {
+    if !(p > 0.0 && p < 1.0 && df > 0.0) {
+        return f64::NAN;
+    }
+    if p == 0.5 {
+        return 0.0;
+    }
+    // For t >= 0, P(|T| > t) = I_x(df / 2, 1 / 2) with x = df / (df + t^2).
+    // `I_x` is increasing in `x`, so bisect on `x` until it is pinned down to
+    // machine precision.
+    let tail = 2.0 * p.min(1.0 - p);
+    let (mut lo, mut hi) = (0.0f64, 1.0f64);
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if mid <= lo || mid >= hi {
+            break;
+        }
+        if beta_inc(0.5 * df, 0.5, mid) < tail {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let x = 0.5 * (lo + hi);
+    let t = (df * (1.0 - x) / x).sqrt();
+    if p < 0.5 {
+        -t
+    } else {
+        t
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{beta_inc, ln_gamma, student_t_quantile};
+
+    #[test]
+    fn gamma() {
+        assert!((ln_gamma(1.0)).abs() < 1e-14);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-13);
+        assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-13);
+    }
+
+    #[test]
+    fn incomplete_beta() {
+        assert!((beta_inc(1.0, 1.0, 0.3) - 0.3).abs() < 1e-14);
+        assert!((beta_inc(2.0, 3.0, 0.4) - 0.5248).abs() < 1e-13);
+        assert!((beta_inc(0.5, 0.5, 0.5) - 0.5).abs() < 1e-13);
+    }
+
+    #[test]
+    fn t_quantile() {
+        assert!((student_t_quantile(0.975, 1.0) - 12.706_204_736_174_707).abs() < 1e-9);
+        assert!((student_t_quantile(0.975, 9.0) - 2.262_157_162_740_992).abs() < 1e-9);
+        assert!((student_t_quantile(0.05, 30.0) + 1.697_260_886_593_958_7).abs() < 1e-9);
+        assert!(student_t_quantile(1.0, 5.0).is_nan());
+    }
+}
@@ -0,0 +1,417 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::Commute;
+
+/// The default compression factor, which bounds a digest to roughly a
+/// hundred centroids.
+const DEFAULT_COMPRESSION: f64 = 100.0;
+
+/// Compute an approximate quantile (`0 <= q <= 1`) on a stream of data using
+/// a t-digest.
+///
+/// (This has time complexity `O(n)` and bounded space complexity.)
+pub fn approx_quantile<I>(it: I, q: f64) -> Option<f64>
+where
+    I: Iterator,
+    <I as Iterator>::Item: ToPrimitive,
+{
+    it.collect::<TDigest>().quantile(q)
+}
+
+/// A weighted point summarizing one or more samples.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A commutative data structure for approximate quantiles in bounded memory.
+///
+/// This is the merging t-digest from Dunning, T. and Ertl, O. (2019),
+/// "Computing Extremely Accurate Quantiles Using t-Digests", using the `k1`
+/// (arcsine) scale function. Accuracy is best near the tails and the digest
+/// keeps at most `O(compression)` centroids, no matter how many samples are
+/// added.
+///
+/// Samples are buffered and only merged into the centroids when the buffer
+/// fills up or statistics need to be computed. `NaN` samples are ignored.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    /// Create an empty digest with the default compression factor (`100`).
+    #[must_use]
+    pub fn new() -> TDigest {
+        Default::default()
+    }
+
+    /// Create an empty digest with the given compression factor.
+    ///
+    /// Higher values are more accurate but use more memory. Values below
+    /// `10` are raised to `10`.
+    #[must_use]
+    pub fn with_compression(compression: f64) -> TDigest {
+        TDigest {
+            compression: compression.max(10.0),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the compression factor.
+    #[inline]
+    #[must_use]
+    pub const fn compression(&self) -> f64 {
+        self.compression
+    }
+
+    /// Add a sample to the digest.
+    #[inline]
+    pub fn add<T: ToPrimitive>(&mut self, sample: &T) {
+        let sample = sample.to_f64().unwrap();
+        if sample.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.buffer.push(sample);
+        if self.buffer.len() >= self.buffer_capacity() {
+            self.flush();
+        }
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Returns true if there are no data points.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the minimum of the data set.
+    #[inline]
+    #[must_use]
+    pub fn min(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.min)
+    }
+
+    /// Returns the maximum of the data set.
+    #[inline]
+    #[must_use]
+    pub fn max(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.max)
+    }
+
+    /// Returns the approximate `q`-th quantile (`0 <= q <= 1`) of the data.
+    ///
+    /// `None` is returned if there is no data or `q` is out of range.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        if self.is_empty() || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        self.flush();
+        let total = self.count as f64;
+        let target = q * total;
+
+        // Each centroid's mean is treated as sitting at the middle of its
+        // weight, and the quantile is interpolated between neighbouring
+        // centroids (or the min/max at the edges).
+        let first = self.centroids.first()?;
+        if target <= first.weight / 2.0 {
+            return Some(interpolate(
+                0.0,
+                self.min,
+                first.weight / 2.0,
+                first.mean,
+                target,
+            ));
+        }
+        let mut cumulative = 0.0;
+        for pair in self.centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let left_center = cumulative + left.weight / 2.0;
+            let right_center = cumulative + left.weight + right.weight / 2.0;
+            if target <= right_center {
+                return Some(interpolate(
+                    left_center,
+                    left.mean,
+                    right_center,
+                    right.mean,
+                    target,
+                ));
+            }
+            cumulative += left.weight;
+        }
+        let last = self.centroids.last()?;
+        Some(interpolate(
+            total - last.weight / 2.0,
+            last.mean,
+            total,
+            self.max,
+            target,
+        ))
+    }
+
+    /// Returns the approximate fraction of the data that is `<= x`.
+    ///
+    /// `None` is returned if there is no data or `x` is `NaN`.
+    pub fn cdf(&mut self, x: f64) -> Option<f64> {
+        if self.is_empty() || x.is_nan() {
+            return None;
+        }
+        if x < self.min {
+            return Some(0.0);
+        }
+        if x >= self.max {
+            return Some(1.0);
+        }
+        self.flush();
+        let total = self.count as f64;
+
+        let first = self.centroids.first()?;
+        if x <= first.mean {
+            return Some(interpolate(self.min, 0.0, first.mean, first.weight / 2.0, x) / total);
+        }
+        let mut cumulative = 0.0;
+        for pair in self.centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            if x <= right.mean {
+                let left_center = cumulative + left.weight / 2.0;
+                let right_center = cumulative + left.weight + right.weight / 2.0;
+                return Some(
+                    interpolate(left.mean, left_center, right.mean, right_center, x) / total,
+                );
+            }
+            cumulative += left.weight;
+        }
+        let last = self.centroids.last()?;
+        Some(interpolate(last.mean, total - last.weight / 2.0, self.max, total, x) / total)
+    }
+
+    /// Returns the approximate median of the data.
+    #[inline]
+    pub fn median(&mut self) -> Option<f64> {
+        self.quantile(0.5)
+    }
+
+    /// Returns the approximate quartiles of the data.
+    #[inline]
+    pub fn quartiles(&mut self) -> Option<(f64, f64, f64)> {
+        Some((
+            self.quantile(0.25)?,
+            self.quantile(0.5)?,
+            self.quantile(0.75)?,
+        ))
+    }
+
+    #[inline]
+    fn buffer_capacity(&self) -> usize {
+        (self.compression * 5.0) as usize
+    }
+
+    /// The `k1` scale function, which maps a quantile to a centroid index.
+    #[inline]
+    fn scale(&self, q: f64) -> f64 {
+        self.compression / (2.0 * PI) * 2.0f64.mul_add(q, -1.0).asin()
+    }
+
+    /// Merges any buffered samples into the centroids.
+    #[inline]
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            self.compress();
+        }
+    }
+
+    /// Merges the buffered samples and the existing centroids into a new set
+    /// of centroids.
+    fn compress(&mut self) {
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1.0 }),
+        );
+        all.sort_unstable_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut merged = Vec::with_capacity(self.compression as usize);
+        let mut iter = all.into_iter();
+        let Some(mut current) = iter.next() else {
+            return;
+        };
+        let mut weight_so_far = 0.0;
+        let mut k_lower = self.scale(0.0);
+        for next in iter {
+            let proposed = current.weight + next.weight;
+            // A centroid may grow as long as it spans at most one unit of
+            // the scale function.
+            if self.scale((weight_so_far + proposed) / total) - k_lower <= 1.0 {
+                current.mean += (next.mean - current.mean) * next.weight / proposed;
+                current.weight = proposed;
+            } else {
+                weight_so_far += current.weight;
+                k_lower = self.scale(weight_so_far / total);
+                merged.push(current);
+                current = next;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+}
+
+/// Linearly interpolates the value at `x` on the line through `(x0, y0)` and
+/// `(x1, y1)`.
+#[inline]
+fn interpolate(x0: f64, y0: f64, x1: f64, y1: f64, x: f64) -> f64 {
+    if x1 <= x0 {
+        return y0;
+    }
+    ((x - x0) / (x1 - x0)).mul_add(y1 - y0, y0)
+}
+
+impl Commute for TDigest {
+    #[inline]
+    fn merge(&mut self, v: TDigest) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        self.count += v.count;
+        self.min = self.min.min(v.min);
+        self.max = self.max.max(v.max);
+        self.compression = self.compression.max(v.compression);
+        // The other digest's centroids are recompressed together with our
+        // own, as if they had been added as (weighted) samples.
+        self.centroids.extend(v.centroids);
+        self.buffer.extend(v.buffer);
+        self.compress();
+    }
+}
+
+impl Default for TDigest {
+    #[inline]
+    fn default() -> TDigest {
+        TDigest {
+            compression: DEFAULT_COMPRESSION,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl fmt::Debug for TDigest {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TDigest(n={}, centroids={}, buffered={})",
+            self.count,
+            self.centroids.len(),
+            self.buffer.len()
+        )
+    }
+}
+
+impl<T: ToPrimitive> FromIterator<T> for TDigest {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> TDigest {
+        let mut v = TDigest::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: ToPrimitive> Extend<T> for TDigest {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(&sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{approx_quantile, TDigest};
+    use crate::{merge_all, Commute};
+
+    #[test]
+    fn tdigest_small() {
+        let mut digest: TDigest = vec![3usize, 1, 2].into_iter().collect();
+        assert_eq!(digest.len(), 3);
+        assert_eq!(digest.quantile(0.0), Some(1.0));
+        assert_eq!(digest.median(), Some(2.0));
+        assert_eq!(digest.quantile(1.0), Some(3.0));
+        assert_eq!(digest.cdf(0.0), Some(0.0));
+        assert_eq!(digest.cdf(3.0), Some(1.0));
+        assert_eq!(digest.quantile(1.5), None);
+        assert_eq!(TDigest::new().median(), None);
+    }
+
+    #[test]
+    fn tdigest_uniform() {
+        let mut digest: TDigest = (0..100_000).collect();
+        for q in [0.001, 0.01, 0.25, 0.5, 0.75, 0.99, 0.999] {
+            let got = digest.quantile(q).unwrap();
+            assert!((got - q * 100_000.0).abs() < 100.0, "q={q}: {got}");
+            let cdf = digest.cdf(q * 100_000.0).unwrap();
+            assert!((cdf - q).abs() < 0.001, "q={q}: {cdf}");
+        }
+        let (q1, q2, q3) = digest.quartiles().unwrap();
+        assert!(q1 < q2 && q2 < q3);
+        assert!(approx_quantile((0..1_000).map(|i| i % 7), 0.5).unwrap() <= 3.5);
+    }
+
+    #[test]
+    fn tdigest_bounded() {
+        let mut digest: TDigest = (0..1_000_000u64).map(|i| (i * 7_919) % 10_007).collect();
+        digest.flush();
+        assert!(digest.centroids.len() <= 2 * digest.compression() as usize);
+        assert_eq!(digest.len(), 1_000_000);
+    }
+
+    #[test]
+    fn tdigest_merge() {
+        let chunks: Vec<TDigest> = (0..10)
+            .map(|c| (c * 10_000..(c + 1) * 10_000).collect())
+            .collect();
+        let mut merged = merge_all(chunks.into_iter()).unwrap();
+        let mut single: TDigest = (0..100_000).collect();
+        assert_eq!(merged.len(), single.len());
+        assert_eq!(merged.min(), Some(0.0));
+        assert_eq!(merged.max(), Some(99_999.0));
+        for q in [0.01, 0.1, 0.5, 0.9, 0.99] {
+            let (a, b) = (merged.quantile(q).unwrap(), single.quantile(q).unwrap());
+            assert!((a - b).abs() < 200.0, "q={q}: {a} vs {b}");
+        }
+
+        let mut empty = TDigest::default();
+        empty.merge(TDigest::with_compression(200.0));
+        assert!(empty.is_empty());
+    }
+}
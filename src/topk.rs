@@ -0,0 +1,398 @@
+use ahash::AHashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::Commute;
+
+/// The default number of counters.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// An estimated count of a value in a `TopK` summary.
+///
+/// The true count is between `count - error` and `count`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CountEstimate {
+    /// The estimated count, which never underestimates the true count.
+    pub count: u64,
+    /// The maximum amount by which `count` overestimates the true count.
+    pub error: u64,
+}
+
+impl CountEstimate {
+    /// Returns the guaranteed minimum of the true count.
+    #[inline]
+    #[must_use]
+    pub const fn lower_bound(&self) -> u64 {
+        self.count - self.error
+    }
+
+    /// Returns the guaranteed maximum of the true count.
+    #[inline]
+    #[must_use]
+    pub const fn upper_bound(&self) -> u64 {
+        self.count
+    }
+}
+
+/// A commutative data structure for the approximate most frequent values
+/// (heavy hitters) in bounded memory.
+///
+/// This is the Space-Saving algorithm from Metwally, A. et al. (2005),
+/// "Efficient Computation of Frequent and Top-k Elements in Data Streams".
+/// With `capacity` counters, every value occurring more than
+/// `len() / capacity` times is guaranteed to be monitored, and each counter
+/// reports how much it may overestimate its value's count.
+///
+/// The counters are kept in a min-heap indexed by value, so adding a sample
+/// takes `O(log capacity)` time, even when it evicts the smallest counter.
+///
+/// Summaries are merged as described in Agarwal, P. et al. (2012),
+/// "Mergeable Summaries", which keeps the same guarantees.
+#[derive(Clone)]
+pub struct TopK<T> {
+    capacity: usize,
+    total: u64,
+    /// The most times a value that was never monitored may have occurred,
+    /// which is only non-zero after merging.
+    floor: u64,
+    /// The counters, as a binary min-heap ordered by count.
+    heap: Vec<(T, CountEstimate)>,
+    /// The position of each monitored value in `heap`.
+    index: AHashMap<T, usize>,
+}
+
+impl<T: Eq + Hash + Clone> TopK<T> {
+    /// Create an empty summary with the default capacity (`100` counters).
+    #[must_use]
+    pub fn new() -> TopK<T> {
+        Default::default()
+    }
+
+    /// Create an empty summary with `capacity` counters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> TopK<T> {
+        assert!(capacity > 0, "TopK capacity must be positive");
+        TopK {
+            capacity,
+            total: 0,
+            floor: 0,
+            heap: Vec::with_capacity(capacity),
+            index: AHashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Add a sample to the summary.
+    #[inline]
+    pub fn add(&mut self, v: T) {
+        self.total += 1;
+        if let Some(&i) = self.index.get(&v) {
+            self.heap[i].1.count += 1;
+            self.sift_down(i);
+            return;
+        }
+        // A new value may have occurred up to `error_bound` times before.
+        let error = self.error_bound();
+        let counter = CountEstimate {
+            count: error + 1,
+            error,
+        };
+        if self.heap.len() >= self.capacity {
+            // Replace the value with the smallest count.
+            let (evicted, _) = std::mem::replace(&mut self.heap[0], (v.clone(), counter));
+            self.index.remove(&evicted);
+            self.index.insert(v, 0);
+            self.sift_down(0);
+        } else {
+            self.heap.push((v.clone(), counter));
+            self.index.insert(v, self.heap.len() - 1);
+            self.sift_up(self.heap.len() - 1);
+        }
+    }
+
+    /// Return the estimated count of `v`, or `None` if it is not monitored.
+    ///
+    /// Values that are not monitored occur at most `error_bound()` times.
+    #[inline]
+    #[must_use]
+    pub fn count(&self, v: &T) -> Option<CountEstimate> {
+        self.index.get(v).map(|&i| self.heap[i].1)
+    }
+
+    /// Return the monitored values and their estimated counts, in descending
+    /// order of count.
+    #[must_use]
+    pub fn most_frequent(&self) -> Vec<(&T, CountEstimate)> {
+        let mut counts: Vec<_> = self.heap.iter().map(|(k, c)| (k, *c)).collect();
+        counts.sort_unstable_by(|(_, c1), (_, c2)| {
+            c2.count.cmp(&c1.count).then(c1.error.cmp(&c2.error))
+        });
+        counts
+    }
+
+    /// Return the maximum error of any count, i.e. the largest number of
+    /// times a value that is not monitored may have occurred.
+    #[inline]
+    #[must_use]
+    pub fn error_bound(&self) -> u64 {
+        if self.heap.len() < self.capacity {
+            self.floor
+        } else {
+            self.heap[0].1.count.max(self.floor)
+        }
+    }
+
+    /// Returns the number of counters.
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of data points.
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.total as usize
+    }
+
+    /// Returns true if there are no data points.
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Moves the counter at `i` up the heap while it is smaller than its
+    /// parent.
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[parent].1.count <= self.heap[i].1.count {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    /// Moves the counter at `i` down the heap while it is larger than one
+    /// of its children.
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (left, right) = (2 * i + 1, 2 * i + 2);
+            let mut smallest = i;
+            if left < self.heap.len() && self.heap[left].1.count < self.heap[smallest].1.count {
+                smallest = left;
+            }
+            if right < self.heap.len() && self.heap[right].1.count < self.heap[smallest].1.count {
+                smallest = right;
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
+    }
+
+    #[inline]
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        *self.index.get_mut(&self.heap[a].0).unwrap() = a;
+        *self.index.get_mut(&self.heap[b].0).unwrap() = b;
+    }
+}
+
+impl<T: Eq + Hash + Clone> Commute for TopK<T> {
+    #[inline]
+    fn merge(&mut self, v: TopK<T>) {
+        if v.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = v;
+            return;
+        }
+        // A value missing from one summary may still have occurred up to
+        // that summary's error bound times in its part of the stream.
+        let (self_bound, other_bound) = (self.error_bound(), v.error_bound());
+        self.index.clear();
+        let mut counters: AHashMap<T, CountEstimate> = self.heap.drain(..).collect();
+        for counter in counters.values_mut() {
+            counter.count += other_bound;
+            counter.error += other_bound;
+        }
+        for (k, c) in v.heap {
+            match counters.get_mut(&k) {
+                Some(counter) => {
+                    // Undo the bound added above and use the real counter.
+                    counter.count = counter.count - other_bound + c.count;
+                    counter.error = counter.error - other_bound + c.error;
+                }
+                None => {
+                    counters.insert(
+                        k,
+                        CountEstimate {
+                            count: c.count + self_bound,
+                            error: c.error + self_bound,
+                        },
+                    );
+                }
+            }
+        }
+        self.total += v.total;
+        self.floor = self_bound + other_bound;
+        self.capacity = self.capacity.max(v.capacity);
+
+        // Keep only the largest counters.
+        if counters.len() > self.capacity {
+            let mut counts: Vec<u64> = counters.values().map(|c| c.count).collect();
+            let idx = counts.len() - self.capacity;
+            let (_, &mut threshold, _) = counts.select_nth_unstable(idx);
+            // Drop everything below the threshold, plus enough ties at the
+            // threshold to get back down to capacity.
+            let below = counters.values().filter(|c| c.count < threshold).count();
+            let mut ties = idx - below;
+            counters.retain(|_, c| {
+                if c.count == threshold && ties > 0 {
+                    ties -= 1;
+                    return false;
+                }
+                c.count >= threshold
+            });
+        }
+
+        // A sorted list is a valid min-heap.
+        self.heap.extend(counters);
+        self.heap.sort_unstable_by_key(|(_, c)| c.count);
+        for (i, (k, _)) in self.heap.iter().enumerate() {
+            self.index.insert(k.clone(), i);
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> Default for TopK<T> {
+    #[inline]
+    fn default() -> TopK<T> {
+        TopK::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl<T: fmt::Debug + Eq + Hash + Clone> fmt::Debug for TopK<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.most_frequent())
+    }
+}
+
+impl<T: Eq + Hash + Clone> FromIterator<T> for TopK<T> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(it: I) -> TopK<T> {
+        let mut v = TopK::new();
+        v.extend(it);
+        v
+    }
+}
+
+impl<T: Eq + Hash + Clone> Extend<T> for TopK<T> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
+        for sample in it {
+            self.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TopK;
+    use crate::{Commute, Frequencies};
+
+    /// A skewed stream where value `i` occurs roughly `1000 / (i + 1)` times.
+    fn zipf(offset: u64) -> Vec<u64> {
+        let mut data = Vec::new();
+        for i in 0..500u64 {
+            for _ in 0..(1_000 / (i + 1)) {
+                data.push(i);
+            }
+        }
+        // Interleave values so the heavy hitters are not all at the front.
+        data.sort_unstable_by_key(|&x| (x * 2_654_435_761 + offset) % 1_000_003);
+        data
+    }
+
+    #[test]
+    fn topk_bounds() {
+        let data = zipf(0);
+        let exact: Frequencies<u64> = data.iter().copied().collect();
+        let mut topk = TopK::with_capacity(50);
+        topk.extend(data.iter().copied());
+
+        assert_eq!(topk.len(), data.len());
+        for (v, estimate) in topk.most_frequent() {
+            let truth = exact.count(v);
+            assert!(estimate.lower_bound() <= truth && truth <= estimate.upper_bound());
+        }
+        // Anything occurring more than n / capacity times must be monitored.
+        let guaranteed = data.len() as u64 / 50;
+        for (v, count) in exact.top_k(10) {
+            assert!(count <= guaranteed || topk.count(v).is_some());
+        }
+        let top: Vec<u64> = topk.most_frequent()[..3].iter().map(|(&v, _)| v).collect();
+        assert_eq!(top, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn topk_exact_when_small() {
+        let topk: TopK<&str> = vec!["a", "b", "a", "c", "a", "b"].into_iter().collect();
+        assert_eq!(topk.error_bound(), 0);
+        let top = topk.most_frequent();
+        assert_eq!(top[0].0, &"a");
+        assert_eq!(top[0].1.count, 3);
+        assert_eq!(top[0].1.error, 0);
+    }
+
+    #[test]
+    fn topk_evictions() {
+        let mut topk = TopK::with_capacity(2);
+        topk.extend(["a", "a", "b", "c"]);
+        // "c" replaces "b", the smallest counter, and inherits its count.
+        assert_eq!(topk.count(&"b"), None);
+        let c = topk.count(&"c").unwrap();
+        assert_eq!((c.count, c.error), (2, 1));
+        assert_eq!(topk.error_bound(), 2);
+
+        topk.extend(["d", "a", "d"]);
+        // "d" replaces "c", then "d" is found again rather than re-added.
+        assert_eq!(topk.count(&"c"), None);
+        let d = topk.count(&"d").unwrap();
+        assert_eq!((d.count, d.error), (4, 2));
+        assert_eq!(topk.count(&"a").unwrap().count, 3);
+        assert_eq!(topk.len(), 7);
+    }
+
+    #[test]
+    fn topk_merge() {
+        let (left, right) = (zipf(0), zipf(7));
+        let exact: Frequencies<u64> = left.iter().chain(right.iter()).copied().collect();
+        let mut a = TopK::with_capacity(50);
+        a.extend(left);
+        let mut b = TopK::with_capacity(50);
+        b.extend(right);
+        a.merge(b);
+
+        assert_eq!(a.len(), exact.most_frequent().1 as usize);
+        assert!(a.most_frequent().len() <= 50);
+        for (v, estimate) in a.most_frequent() {
+            let truth = exact.count(v);
+            assert!(estimate.lower_bound() <= truth && truth <= estimate.upper_bound());
+        }
+        assert_eq!(*a.most_frequent()[0].0, 0);
+
+        a.merge(TopK::default());
+        assert_eq!(a.len(), exact.most_frequent().1 as usize);
+    }
+}
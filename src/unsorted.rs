@@ -1,14 +1,18 @@
 use num_traits::ToPrimitive;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use rayon::slice::ParallelSliceMut;
+use rayon::iter::{
+    FromParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use rayon::slice::{ParallelSlice, ParallelSliceMut};
 
 use serde::{Deserialize, Serialize};
 
-use {crate::Commute, crate::Partial};
+use crate::outliers::{Fences, OutlierRule, Outliers};
+use {crate::is_nan, crate::special, crate::NanPolicy};
+use {crate::Commute, crate::OnlineStats, crate::Partial, crate::PAR_CHUNK_SIZE};
 
 /// Compute the exact median on a stream of data.
 ///
-/// (This has time complexity `O(nlogn)` and space complexity `O(n)`.)
+/// (This has expected time complexity `O(n)` and space complexity `O(n)`.)
 pub fn median<I>(it: I) -> Option<f64>
 where
     I: Iterator,
@@ -28,7 +32,7 @@ where
 
 /// Compute the exact 1-, 2-, and 3-quartiles (Q1, Q2 a.k.a. median, and Q3) on a stream of data.
 ///
-/// (This has time complexity `O(nlogn)` and space complexity `O(n)`.)
+/// (This has expected time complexity `O(n)` and space complexity `O(n)`.)
 pub fn quartiles<I>(it: I) -> Option<(f64, f64, f64)>
 where
     I: Iterator,
@@ -37,6 +41,18 @@ where
     it.collect::<Unsorted<_>>().quartiles()
 }
 
+/// Compute the exact `p`-th percentile (`0 <= p <= 100`) on a stream of data,
+/// using the given interpolation `method`.
+///
+/// (This has expected time complexity `O(n)` and space complexity `O(n)`.)
+pub fn percentile<I>(it: I, p: f64, method: QuantileMethod) -> Option<f64>
+where
+    I: Iterator,
+    <I as Iterator>::Item: PartialOrd + ToPrimitive,
+{
+    it.collect::<Unsorted<_>>().percentile(p, method)
+}
+
 /// Compute the exact mode on a stream of data.
 ///
 /// (This has time complexity `O(nlogn)` and space complexity `O(n)`.)
@@ -124,15 +140,184 @@ where
     })
 }
 
-fn mad_on_sorted<T>(data: &[T], precalc_median: Option<f64>) -> Option<f64>
+/// The method used to compute a sample quantile from sorted data.
+///
+/// These are the nine definitions from Hyndman, R. J. and Fan, Y. (1996),
+/// "Sample quantiles in statistical packages", numbered as in R's
+/// `quantile(type = ...)`. The names follow NumPy's `quantile(method = ...)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuantileMethod {
+    /// R type 1: inverse of the empirical distribution function.
+    InvertedCdf,
+    /// R type 2: like type 1, but averages at discontinuities.
+    AveragedInvertedCdf,
+    /// R type 3: the nearest order statistic, ties to even (SAS definition 2).
+    ClosestObservation,
+    /// R type 4: linear interpolation of the empirical CDF.
+    InterpolatedInvertedCdf,
+    /// R type 5: piecewise linear with knots at the midpoints of the steps.
+    Hazen,
+    /// R type 6: `p(k) = k / (n + 1)`, as used by Minitab, SPSS and Excel's
+    /// `PERCENTILE.EXC`.
+    Weibull,
+    /// R type 7: `p(k) = (k - 1) / (n - 1)`, the default in R, NumPy and
+    /// Excel's `PERCENTILE.INC`.
+    #[default]
+    Linear,
+    /// R type 8: approximately median-unbiased regardless of the distribution.
+    MedianUnbiased,
+    /// R type 9: approximately unbiased if the data is normally distributed.
+    NormalUnbiased,
+}
+
+/// The 0-based order statistics `(lo, hi)` and the weight `gamma` such that
+/// the quantile is `(1 - gamma) * x_lo + gamma * x_hi`.
+#[derive(Clone, Copy)]
+struct QuantilePosition {
+    lo: usize,
+    hi: usize,
+    gamma: f64,
+}
+
+fn quantile_position(len: usize, q: f64, method: QuantileMethod) -> Option<QuantilePosition> {
+    // Same tolerance as R, so that e.g. `10 * 0.3` is treated as exactly `3`.
+    const FUZZ: f64 = 4.0 * f64::EPSILON;
+
+    if len == 0 || !(0.0..=1.0).contains(&q) {
+        return None;
+    }
+    let n = len as f64;
+    // Every method is of the form `Q = (1 - gamma) * x_j + gamma * x_{j+1}`
+    // with `j = floor(n * q + m)` (1-based), where `m` and `gamma` depend
+    // on the method.
+    let m = match method {
+        QuantileMethod::InvertedCdf
+        | QuantileMethod::AveragedInvertedCdf
+        | QuantileMethod::InterpolatedInvertedCdf => 0.0,
+        QuantileMethod::ClosestObservation => -0.5,
+        QuantileMethod::Hazen => 0.5,
+        QuantileMethod::Weibull => q,
+        QuantileMethod::Linear => 1.0 - q,
+        QuantileMethod::MedianUnbiased => (q + 1.0) / 3.0,
+        QuantileMethod::NormalUnbiased => q.mul_add(0.25, 0.375),
+    };
+    let h = n.mul_add(q, m);
+    let j = (h + FUZZ).floor();
+    let mut g = h - j;
+    if g.abs() < FUZZ {
+        g = 0.0;
+    }
+    let gamma = match method {
+        QuantileMethod::InvertedCdf => {
+            if g > 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        QuantileMethod::AveragedInvertedCdf => {
+            if g > 0.0 {
+                1.0
+            } else {
+                0.5
+            }
+        }
+        QuantileMethod::ClosestObservation => {
+            if g == 0.0 && j % 2.0 == 0.0 {
+                0.0
+            } else {
+                1.0
+            }
+        }
+        _ => g,
+    };
+
+    // Convert the 1-based `j` and `j + 1` to 0-based indices, clamped to the
+    // data, i.e. `x_0 = x_1` and `x_{n+1} = x_n`.
+    let last = (len - 1) as f64;
+    Some(QuantilePosition {
+        lo: (j - 1.0).clamp(0.0, last) as usize,
+        hi: j.clamp(0.0, last) as usize,
+        gamma,
+    })
+}
+
+/// Computes a quantile from `data`, which only needs to hold the correct
+/// order statistics at the positions referenced by `pos`.
+fn quantile_at<T>(data: &[T], pos: QuantilePosition) -> Option<f64>
+where
+    T: ToPrimitive,
+{
+    let x_lo = data.get(pos.lo)?.to_f64().unwrap();
+    if pos.gamma == 0.0 {
+        return Some(x_lo);
+    }
+    let x_hi = data.get(pos.hi)?.to_f64().unwrap();
+    if pos.gamma == 1.0 {
+        return Some(x_hi);
+    }
+    Some(pos.gamma.mul_add(x_hi - x_lo, x_lo))
+}
+
+/// Partially sorts `data` so that, for every index `k` in `ks`, `data[k]` is
+/// the element that would be there if `data` were fully sorted.
+///
+/// `ks` must be sorted in ascending order, without duplicates, and offset by
+/// `offset` relative to `data`. Each level of recursion performs one
+/// quickselect, so the expected time complexity is `O(n log k)` for `k`
+/// indices instead of `O(n log n)` for a full sort.
+fn select_many<T: Ord>(data: &mut [T], ks: &[usize], offset: usize) {
+    if ks.is_empty() || data.len() <= 1 {
+        return;
+    }
+    let mid = ks.len() / 2;
+    let k = ks[mid] - offset;
+    let (left, _, right) = data.select_nth_unstable(k);
+    select_many(left, &ks[..mid], offset);
+    select_many(right, &ks[mid + 1..], offset + k + 1);
+}
+
+fn median_indices(len: usize) -> Vec<usize> {
+    match len {
+        0 => vec![],
+        len if len % 2 == 0 => vec![len / 2 - 1, len / 2],
+        len => vec![len / 2],
+    }
+}
+
+/// The indices read by `quartiles_on_sorted` for data of length `len`.
+fn quartile_indices(len: usize) -> Vec<usize> {
+    match len {
+        0..=2 => vec![],
+        3 => vec![0, 1, 2],
+        len => {
+            let r = len % 4;
+            let k = (len - r) / 4;
+            match r {
+                0 => vec![k - 1, k, 2 * k - 1, 2 * k, 3 * k - 1, 3 * k],
+                1 => vec![k - 1, k, 2 * k, 3 * k, 3 * k + 1],
+                2 => vec![k, 2 * k, 2 * k + 1, 3 * k + 1],
+                _ => vec![k, 2 * k + 1, 3 * k + 2],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn quantile_on_sorted<T>(data: &[T], q: f64, method: QuantileMethod) -> Option<f64>
+where
+    T: ToPrimitive,
+{
+    quantile_at(data, quantile_position(data.len(), q, method)?)
+}
+
+fn mad_around<T>(data: &[T], median_obs: f64) -> Option<f64>
 where
     T: Sync + PartialOrd + ToPrimitive,
 {
     if data.is_empty() {
         return None;
     }
-    let median_obs =
-        precalc_median.map_or_else(|| median_on_sorted(data).unwrap(), |precalc| precalc);
 
     let mut abs_diff_vec: Vec<f64> = data
         .par_iter()
@@ -142,8 +327,15 @@ where
         })
         .collect();
 
-    abs_diff_vec.par_sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
-    median_on_sorted(&abs_diff_vec)
+    // We only need the middle order statistic(s), so select instead of sort.
+    let len = abs_diff_vec.len();
+    let (left, upper, _) = abs_diff_vec.select_nth_unstable_by(len / 2, f64::total_cmp);
+    let upper = *upper;
+    if len % 2 == 1 {
+        return Some(upper);
+    }
+    let lower = left.iter().copied().max_by(f64::total_cmp)?;
+    Some((lower + upper) / 2.0)
 }
 
 fn quartiles_on_sorted<T>(data: &[T]) -> Option<(f64, f64, f64)>
@@ -240,9 +432,9 @@ where
     let (mut mode, mut next) = (None, None);
     let (mut mode_count, mut next_count) = (0usize, 0usize);
     for x in it {
-        if mode.as_ref().map_or(false, |y| y == &x) {
+        if mode.as_ref() == Some(&x) {
             mode_count += 1;
-        } else if next.as_ref().map_or(false, |y| y == &x) {
+        } else if next.as_ref() == Some(&x) {
             next_count += 1;
         } else {
             next = Some(x);
@@ -373,19 +565,105 @@ where
     (antimodes_result, antimodes_count, lowest_mode)
 }
 
+/// Computes the mean of sorted `data` after removing `g` (which need not be
+/// an integer) data points from each end, counting partially removed data
+/// points in proportion to what is left of them.
+fn trimmed_mean_on_sorted<T>(data: &[T], g: f64) -> Option<f64>
+where
+    T: ToPrimitive,
+{
+    let n = data.len() as f64;
+    if data.is_empty() || n - 2.0 * g <= 0.0 {
+        return None;
+    }
+    let (lo, hi) = (g.floor() as usize, (n - g).ceil() as usize);
+    let sum: f64 = data[lo..hi]
+        .iter()
+        .zip(lo..)
+        .map(|(x, i)| {
+            // The overlap of `[i, i + 1]` with the kept range `[g, n - g]`.
+            let i = i as f64;
+            let weight = (i + 1.0).min(n - g) - i.max(g);
+            weight * x.to_f64().unwrap()
+        })
+        .sum();
+    Some(sum / 2.0f64.mul_add(-g, n))
+}
+
+/// Maps a float to an integer with the same (total) order.
+#[inline]
+fn ordered_bits(x: f64) -> i64 {
+    let bits = x.to_bits() as i64;
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
+}
+
+/// Returns the `k`-th smallest (1-based) pairwise sum `x_i + x_j` with
+/// `i <= j` of sorted `data`.
+///
+/// Instead of materializing all `n (n + 1) / 2` sums, this bisects on the
+/// bits of the result, counting the sums no larger than each candidate in
+/// `O(n)` time, so it uses `O(n)` time per bit and constant space.
+fn kth_pairwise_sum(data: &[f64], k: usize) -> f64 {
+    let count_le = |s: f64| {
+        let (mut i, mut j, mut count) = (0, data.len(), 0);
+        while i < j {
+            if data[i] + data[j - 1] <= s {
+                // `x_i` plus anything in `x_i..=x_{j-1}` is small enough.
+                count += j - i;
+                i += 1;
+            } else {
+                j -= 1;
+            }
+        }
+        count
+    };
+    let from_bits = |b: i64| f64::from_bits(ordered_bits(f64::from_bits(b as u64)) as u64);
+    let (mut lo, mut hi) = (
+        ordered_bits(data[0] + data[0]),
+        ordered_bits(data[data.len() - 1] + data[data.len() - 1]),
+    );
+    // The count only increases at actual sums, so the smallest candidate
+    // with at least `k` sums at or below it is the `k`-th sum.
+    while lo < hi {
+        let mid = ((i128::from(lo) + i128::from(hi)) >> 1) as i64;
+        if count_le(from_bits(mid)) >= k {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    from_bits(lo)
+}
+
 /// A commutative data structure for lazily sorted sequences of data.
 ///
 /// The sort does not occur until statistics need to be computed.
 ///
 /// Note that this works on types that do not define a total ordering like
-/// `f32` and `f64`. When an ordering is not defined, an arbitrary order
-/// is returned.
-#[derive(Clone, Serialize, Deserialize, Eq, PartialEq)]
+/// `f32` and `f64`. `NaN`s are handled according to the `NanPolicy`: by
+/// default, they are sorted after every other value. With
+/// `NanPolicy::Propagate`, any `NaN` makes the numeric statistics (median,
+/// quantiles, robust means, etc.) `NaN`.
+///
+/// Elements may be weighted with `add_weighted`. Weights are only used by
+/// `weighted_quantile` and `weighted_median`; every other statistic treats
+/// each element as a single data point.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct Unsorted<T> {
     data: Vec<Partial<T>>,
     sorted: bool,
+    /// The weight of each element in `data`, or empty if every weight is `1`.
+    #[serde(default)]
+    weights: Vec<f64>,
+    #[serde(default)]
+    nan_policy: NanPolicy,
+    #[serde(default)]
+    nans: u64,
 }
 
+// Weights are never `NaN`, so equality is reflexive.
+impl<T: Eq> Eq for Unsorted<T> {}
+
 impl<T: PartialOrd> Unsorted<T> {
     /// Create initial empty state.
     #[inline]
@@ -394,11 +672,132 @@ impl<T: PartialOrd> Unsorted<T> {
         Default::default()
     }
 
+    /// Create initial empty state that handles `NaN`s according to
+    /// `nan_policy`.
+    #[inline]
+    #[must_use]
+    pub fn with_nan_policy(nan_policy: NanPolicy) -> Unsorted<T> {
+        Unsorted {
+            nan_policy,
+            ..Default::default()
+        }
+    }
+
+    /// Create a state holding a copy of `samples`, splitting the work
+    /// across the rayon thread pool.
+    #[must_use]
+    pub fn par_from_slice(samples: &[T]) -> Unsorted<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        samples
+            .par_chunks(PAR_CHUNK_SIZE)
+            .map(|chunk| chunk.iter().cloned().collect())
+            .reduce(Unsorted::empty, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+
+    /// An empty state that, unlike `Default`, does not preallocate, for the
+    /// identities of parallel folds, where every split creates one.
+    #[inline]
+    fn empty() -> Unsorted<T> {
+        Unsorted {
+            data: Vec::new(),
+            sorted: true, // empty is sorted
+            weights: Vec::new(),
+            nan_policy: NanPolicy::default(),
+            nans: 0,
+        }
+    }
+
     /// Add a new element to the set.
     #[inline]
     pub fn add(&mut self, v: T) {
+        if self.rejects(&v) {
+            return;
+        }
+        self.sorted = false;
+        self.data.push(Partial(v));
+        if !self.weights.is_empty() {
+            self.weights.push(1.0);
+        }
+    }
+
+    /// Add a new element with the given weight to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    #[inline]
+    pub fn add_weighted(&mut self, v: T, weight: f64) {
+        assert!(
+            weight >= 0.0 && weight.is_finite(),
+            "weight must be finite and non-negative"
+        );
+        if self.rejects(&v) {
+            return;
+        }
+        self.track_weights();
         self.sorted = false;
         self.data.push(Partial(v));
+        self.weights.push(weight);
+    }
+
+    /// Counts `NaN`s, and returns true if `v` should be skipped.
+    #[inline]
+    fn rejects(&mut self, v: &T) -> bool {
+        // A value that is ordered with respect to the last element is not
+        // `NaN`, and comparing with it usually stops sooner than comparing
+        // `v` with itself, e.g. for strings.
+        if self
+            .data
+            .last()
+            .is_some_and(|last| v.partial_cmp(&last.0).is_some())
+        {
+            return false;
+        }
+        if is_nan(v) {
+            self.nans += 1;
+            return self.nan_policy == NanPolicy::Skip;
+        }
+        false
+    }
+
+    /// Returns true if a `NaN` should make every numeric statistic `NaN`.
+    #[inline]
+    fn propagates_nan(&self) -> bool {
+        self.nan_policy == NanPolicy::Propagate && self.nans > 0
+    }
+
+    /// Returns the policy for `NaN`s.
+    #[inline]
+    #[must_use]
+    pub const fn nan_policy(&self) -> NanPolicy {
+        self.nan_policy
+    }
+
+    /// Returns the number of `NaN`s added, which were not counted as data
+    /// points if the policy is `NanPolicy::Skip`.
+    #[inline]
+    #[must_use]
+    pub const fn nan_count(&self) -> usize {
+        self.nans as usize
+    }
+
+    /// Starts tracking weights, giving every existing element weight `1`.
+    #[inline]
+    fn track_weights(&mut self) {
+        if self.weights.is_empty() {
+            self.weights.resize(self.data.len(), 1.0);
+        }
+    }
+
+    /// Returns the data points, in no particular order.
+    #[inline]
+    pub(crate) fn values(&self) -> impl Iterator<Item = &T> {
+        self.data.iter().map(|v| &v.0)
     }
 
     /// Return the number of data points.
@@ -409,13 +808,65 @@ impl<T: PartialOrd> Unsorted<T> {
         self.data.len()
     }
 
+    /// Returns the sum of the weights, which is `len()` unless elements were
+    /// added with `add_weighted`.
+    #[inline]
+    #[must_use]
+    pub fn weight(&self) -> f64 {
+        if self.weights.is_empty() {
+            self.data.len() as f64
+        } else {
+            self.weights.iter().sum()
+        }
+    }
+
     #[inline]
     fn sort(&mut self) {
         if !self.sorted {
-            self.data.par_sort_unstable();
+            if self.weights.is_empty() {
+                self.data.par_sort_unstable();
+            } else {
+                // Keep each weight with its element.
+                let mut pairs: Vec<_> = self.data.drain(..).zip(self.weights.drain(..)).collect();
+                pairs.par_sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+                (self.data, self.weights) = pairs.into_iter().unzip();
+            }
             self.sorted = true;
         }
     }
+
+    /// Moves the order statistics at the indices `ks` into place, without
+    /// sorting the rest of the data. `ks` may be in any order and contain
+    /// duplicates.
+    ///
+    /// This is a no-op if the data is already sorted. Weighted data is
+    /// sorted instead, so that the weights stay with their elements.
+    #[inline]
+    fn select(&mut self, ks: &[usize]) {
+        if !self.weights.is_empty() {
+            self.sort();
+        } else if !self.sorted {
+            // `select_many` needs ascending indices without duplicates.
+            let mut ks = ks.to_vec();
+            ks.sort_unstable();
+            ks.dedup();
+            select_many(&mut self.data, &ks, 0);
+        }
+    }
+
+    /// Returns the `n`-th smallest element (0-based), or `None` if `n` is out
+    /// of range.
+    ///
+    /// Unless the data is already sorted, this uses quickselect, which has
+    /// expected time complexity `O(n)`, instead of sorting the data.
+    #[inline]
+    pub fn select_nth(&mut self, n: usize) -> Option<&T> {
+        if n >= self.data.len() {
+            return None;
+        }
+        self.select(&[n]);
+        self.data.get(n).map(|p| &p.0)
+    }
 }
 
 impl<T: PartialOrd + Eq + Clone> Unsorted<T> {
@@ -459,9 +910,15 @@ impl<T: PartialOrd + Clone> Unsorted<T> {
 
 impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
     /// Returns the median of the data.
+    ///
+    /// Unless the data is already sorted, this uses quickselect instead of
+    /// sorting the data.
     #[inline]
     pub fn median(&mut self) -> Option<f64> {
-        self.sort();
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        self.select(&median_indices(self.len()));
         median_on_sorted(&self.data)
     }
 }
@@ -470,25 +927,362 @@ impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
     /// Returns the MAD of the data.
     #[inline]
     pub fn mad(&mut self, existing_median: Option<f64>) -> Option<f64> {
-        if existing_median.is_none() {
-            self.sort();
+        if self.propagates_nan() {
+            return Some(f64::NAN);
         }
-        mad_on_sorted(&self.data, existing_median)
+        let median_obs = match existing_median {
+            Some(median) => median,
+            None => self.median()?,
+        };
+        mad_around(&self.data, median_obs)
     }
 }
 
 impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
     /// Returns the quartiles of the data.
+    ///
+    /// The quartiles are the medians of the lower and upper halves of the
+    /// data, leaving the median itself out of both halves when the number of
+    /// data points is odd. (Tukey's hinges differ from this for odd lengths,
+    /// since they keep the median in both halves.) With three data points,
+    /// the first and third quartiles are the minimum and maximum.
+    ///
+    /// This is not built on `quantiles`, because no single Hyndman-Fan
+    /// definition gives these values for every length: `[1, 2, 3, 4]`
+    /// matches `QuantileMethod::Hazen`, but `[3, 5, 7, 9, 12]` does not.
+    /// Use `quartiles_with` to pick one of the Hyndman-Fan definitions
+    /// instead.
     #[inline]
     pub fn quartiles(&mut self) -> Option<(f64, f64, f64)> {
-        self.sort();
+        if self.propagates_nan() {
+            return (self.len() >= 3).then_some((f64::NAN, f64::NAN, f64::NAN));
+        }
+        self.select(&quartile_indices(self.len()));
         quartiles_on_sorted(&self.data)
     }
+
+    /// Returns the quartiles of the data using the given quantile `method`.
+    #[inline]
+    pub fn quartiles_with(&mut self, method: QuantileMethod) -> Option<(f64, f64, f64)> {
+        match self.quantiles(&[0.25, 0.5, 0.75], method)?[..] {
+            [q1, q2, q3] => Some((q1, q2, q3)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the `q`-th quantile (`0 <= q <= 1`) of the data.
+    ///
+    /// `None` is returned if there is no data or `q` is out of range.
+    #[inline]
+    pub fn quantile(&mut self, q: f64, method: QuantileMethod) -> Option<f64> {
+        let pos = quantile_position(self.len(), q, method)?;
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        if pos.lo == pos.hi {
+            self.select(&[pos.lo]);
+        } else {
+            self.select(&[pos.lo, pos.hi]);
+        }
+        quantile_at(&self.data, pos)
+    }
+
+    /// Returns the `p`-th percentile (`0 <= p <= 100`) of the data.
+    ///
+    /// `None` is returned if there is no data or `p` is out of range.
+    #[inline]
+    pub fn percentile(&mut self, p: f64, method: QuantileMethod) -> Option<f64> {
+        self.quantile(p / 100.0, method)
+    }
+
+    /// Returns the quantiles of the data for each `q` (`0 <= q <= 1`) in `qs`,
+    /// in the same order.
+    ///
+    /// `None` is returned if there is no data or any `q` is out of range.
+    #[inline]
+    pub fn quantiles(&mut self, qs: &[f64], method: QuantileMethod) -> Option<Vec<f64>> {
+        let len = self.len();
+        let positions = qs
+            .iter()
+            .map(|&q| quantile_position(len, q, method))
+            .collect::<Option<Vec<_>>>()?;
+        if self.propagates_nan() {
+            return Some(vec![f64::NAN; qs.len()]);
+        }
+        // All the order statistics are selected in one multi-select pass.
+        let ks: Vec<usize> = positions.iter().flat_map(|p| [p.lo, p.hi]).collect();
+        self.select(&ks);
+        positions
+            .into_iter()
+            .map(|pos| quantile_at(&self.data, pos))
+            .collect()
+    }
+
+    /// Returns the weighted `q`-th quantile (`0 <= q <= 1`) of the data.
+    ///
+    /// This is the smallest element whose cumulative weight is at least `q`
+    /// times the total weight, averaged with the next element when the
+    /// cumulative weight is exactly that. Elements with weight `0` are never
+    /// returned. When all weights are `1`, this is the same as
+    /// `QuantileMethod::AveragedInvertedCdf`, and with integer weights it is
+    /// the same as repeating each element `weight` times.
+    ///
+    /// `None` is returned if the total weight is `0` or `q` is out of range.
+    pub fn weighted_quantile(&mut self, q: f64) -> Option<f64> {
+        // Same tolerance as `quantile_position`, relative to the total weight.
+        const FUZZ: f64 = 4.0 * f64::EPSILON;
+
+        if !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        self.sort();
+        let total = self.weight();
+        if total <= 0.0 {
+            return None;
+        }
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        let (target, tolerance) = (q * total, FUZZ * total);
+        let weight = |i: usize| self.weights.get(i).copied().unwrap_or(1.0);
+        let value = |i: usize| self.data[i].0.to_f64().unwrap();
+        let mut positive = (0..self.data.len()).filter(|&i| weight(i) > 0.0);
+        let mut cumulative = 0.0;
+        let mut last = None;
+        while let Some(i) = positive.next() {
+            cumulative += weight(i);
+            last = Some(i);
+            if cumulative < target - tolerance {
+                continue;
+            }
+            if (cumulative - target).abs() <= tolerance {
+                if let Some(j) = positive.next() {
+                    return Some((value(i) + value(j)) / 2.0);
+                }
+            }
+            return Some(value(i));
+        }
+        last.map(value)
+    }
+
+    /// Returns the weighted median of the data.
+    ///
+    /// This is `weighted_quantile(0.5)`, which is the ordinary median when
+    /// all weights are `1`.
+    #[inline]
+    pub fn weighted_median(&mut self) -> Option<f64> {
+        self.weighted_quantile(0.5)
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
+    /// Returns the mean of the data after removing the smallest and largest
+    /// `fraction` (`0 <= fraction < 0.5`) of it.
+    ///
+    /// `floor(fraction * n)` data points are removed from each end, as in R's
+    /// `mean(x, trim = fraction)`.
+    ///
+    /// `None` is returned if there is no data or `fraction` is out of range.
+    pub fn trimmed_mean(&mut self, fraction: f64) -> Option<f64> {
+        if !(0.0..0.5).contains(&fraction) {
+            return None;
+        }
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        self.sort();
+        let g = (fraction * self.len() as f64).floor();
+        trimmed_mean_on_sorted(&self.data, g)
+    }
+
+    /// Returns the mean of the middle half of the data.
+    ///
+    /// When the number of data points is not a multiple of four, the data
+    /// points at the boundaries are weighted by the fraction of them that
+    /// falls inside the middle half.
+    pub fn interquartile_mean(&mut self) -> Option<f64> {
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        self.sort();
+        let g = self.len() as f64 / 4.0;
+        trimmed_mean_on_sorted(&self.data, g)
+    }
+
+    /// Returns the mean of the data after replacing the smallest and largest
+    /// `fraction` (`0 <= fraction < 0.5`) of it with the nearest remaining
+    /// values.
+    ///
+    /// `None` is returned if there is no data or `fraction` is out of range.
+    pub fn winsorized_mean(&mut self, fraction: f64) -> Option<f64> {
+        let winsorized = self.winsorized(fraction)?;
+        Some(winsorized.iter().sum::<f64>() / winsorized.len() as f64)
+    }
+
+    /// Returns the sample variance (divided by `n - 1`) of the data after
+    /// replacing the smallest and largest `fraction` (`0 <= fraction < 0.5`)
+    /// of it with the nearest remaining values.
+    ///
+    /// `None` is returned if there are fewer than two data points or
+    /// `fraction` is out of range.
+    pub fn winsorized_variance(&mut self, fraction: f64) -> Option<f64> {
+        let winsorized = self.winsorized(fraction)?;
+        let n = winsorized.len() as f64;
+        if n < 2.0 {
+            return None;
+        }
+        let mean = winsorized.iter().sum::<f64>() / n;
+        let q: f64 = winsorized.iter().map(|x| (x - mean) * (x - mean)).sum();
+        Some(q / (n - 1.0))
+    }
+
+    fn winsorized(&mut self, fraction: f64) -> Option<Vec<f64>> {
+        if self.data.is_empty() || !(0.0..0.5).contains(&fraction) {
+            return None;
+        }
+        if self.propagates_nan() {
+            return Some(vec![f64::NAN; self.len()]);
+        }
+        self.sort();
+        let n = self.len();
+        let g = (fraction * n as f64).floor() as usize;
+        let (lo, hi) = (
+            self.data[g].0.to_f64().unwrap(),
+            self.data[n - 1 - g].0.to_f64().unwrap(),
+        );
+        Some(
+            self.data
+                .iter()
+                .map(|x| x.0.to_f64().unwrap().clamp(lo, hi))
+                .collect(),
+        )
+    }
+
+    /// Returns the Hodges-Lehmann estimator of location, i.e. the median of
+    /// the averages of all pairs of data points (including each data point
+    /// with itself).
+    ///
+    /// This takes `O(n log n)` time and `O(n)` space, without computing the
+    /// `n (n + 1) / 2` pairwise averages.
+    pub fn hodges_lehmann(&mut self) -> Option<f64> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        self.sort();
+        let data: Vec<f64> = self.data.iter().map(|x| x.0.to_f64().unwrap()).collect();
+        let n = data.len();
+        let pairs = n * (n + 1) / 2;
+        let sum = if pairs % 2 == 1 {
+            kth_pairwise_sum(&data, pairs / 2 + 1)
+        } else {
+            let lower = kth_pairwise_sum(&data, pairs / 2);
+            let upper = kth_pairwise_sum(&data, pairs / 2 + 1);
+            (lower + upper) / 2.0
+        };
+        Some(sum / 2.0)
+    }
+
+    /// Returns the Harrell-Davis estimate of the `q`-th quantile
+    /// (`0 < q < 1`).
+    ///
+    /// This is a weighted average of all the order statistics, from Harrell,
+    /// F. E. and Davis, C. E. (1982), "A new distribution-free quantile
+    /// estimator", which is smoother and often more efficient than the
+    /// sample quantiles.
+    ///
+    /// `None` is returned if there is no data or `q` is out of range.
+    pub fn harrell_davis_quantile(&mut self, q: f64) -> Option<f64> {
+        if self.data.is_empty() || !(q > 0.0 && q < 1.0) {
+            return None;
+        }
+        if self.propagates_nan() {
+            return Some(f64::NAN);
+        }
+        self.sort();
+        let n = self.len() as f64;
+        let (a, b) = ((n + 1.0) * q, (n + 1.0) * (1.0 - q));
+        let mut previous = 0.0;
+        let mut estimate = 0.0;
+        for (i, x) in self.data.iter().enumerate() {
+            let cdf = special::beta_inc(a, b, (i + 1) as f64 / n);
+            estimate += (cdf - previous) * x.0.to_f64().unwrap();
+            previous = cdf;
+        }
+        Some(estimate)
+    }
+
+    /// Returns the Harrell-Davis estimate of the median.
+    #[inline]
+    pub fn harrell_davis_median(&mut self) -> Option<f64> {
+        self.harrell_davis_quantile(0.5)
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive> Unsorted<T> {
+    /// Returns the fences of the given outlier `rule` for the data.
+    ///
+    /// `None` is returned if the statistics the rule needs are not defined,
+    /// e.g. Tukey's fences need at least three data points.
+    pub fn fences(&mut self, rule: OutlierRule) -> Option<Fences> {
+        match rule {
+            OutlierRule::Tukey { inner, outer } => {
+                let (q1, _, q3) = self.quartiles()?;
+                Some(Fences::tukey(q1, q3, inner, outer))
+            }
+            OutlierRule::ZScore { inner, outer } => {
+                if self.data.is_empty() {
+                    return None;
+                }
+                let stats: OnlineStats = self.data.iter().map(|x| x.0.to_f64().unwrap()).collect();
+                Some(Fences::z_score(&stats, inner, outer))
+            }
+            OutlierRule::ModifiedZScore { inner, outer } => {
+                let median = self.median()?;
+                let mad = self.mad(Some(median))?;
+                Some(Fences::modified_z_score(median, mad, inner, outer))
+            }
+        }
+    }
+}
+
+impl<T: PartialOrd + ToPrimitive + Clone> Unsorted<T> {
+    /// Returns the outliers in the data according to `rule`, in ascending
+    /// order.
+    ///
+    /// `None` is returned if the fences of `rule` are not defined for the
+    /// data.
+    pub fn outliers(&mut self, rule: OutlierRule) -> Option<Outliers<T>> {
+        let mut outliers = Outliers::new(self.fences(rule)?);
+        self.sort();
+        for x in &self.data {
+            outliers.add(x.0.to_f64().unwrap(), x.0.clone());
+        }
+        Some(outliers)
+    }
 }
 
 impl<T: PartialOrd> Commute for Unsorted<T> {
     #[inline]
-    fn merge(&mut self, v: Unsorted<T>) {
+    fn merge(&mut self, mut v: Unsorted<T>) {
+        if v.data.is_empty() && v.nans == 0 {
+            return;
+        }
+        if self.data.is_empty() && self.nans == 0 {
+            self.nan_policy = v.nan_policy;
+        }
+        assert_eq!(
+            self.nan_policy, v.nan_policy,
+            "cannot merge Unsorted with different NaN policies"
+        );
+        self.nans += v.nans;
+        if !self.weights.is_empty() || !v.weights.is_empty() {
+            self.track_weights();
+            v.track_weights();
+            self.weights.extend(v.weights);
+        }
         self.sorted = false;
         self.data.extend(v.data);
     }
@@ -499,7 +1293,7 @@ impl<T: PartialOrd> Default for Unsorted<T> {
     fn default() -> Unsorted<T> {
         Unsorted {
             data: Vec::with_capacity(10_000),
-            sorted: true, // empty is sorted
+            ..Unsorted::empty()
         }
     }
 }
@@ -513,17 +1307,44 @@ impl<T: PartialOrd> FromIterator<T> for Unsorted<T> {
     }
 }
 
+impl<T: PartialOrd + Send> FromParallelIterator<T> for Unsorted<T> {
+    #[inline]
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(it: I) -> Unsorted<T> {
+        it.into_par_iter()
+            .fold(Unsorted::empty, |mut v, sample| {
+                v.add(sample);
+                v
+            })
+            .reduce(Unsorted::empty, |mut v1, v2| {
+                v1.merge(v2);
+                v1
+            })
+    }
+}
+
 impl<T: PartialOrd> Extend<T> for Unsorted<T> {
     #[inline]
     fn extend<I: IntoIterator<Item = T>>(&mut self, it: I) {
         self.sorted = false;
-        self.data.extend(it.into_iter().map(Partial));
+        let it = it.into_iter();
+        self.data.reserve(it.size_hint().0);
+        for v in it {
+            if !self.rejects(&v) {
+                self.data.push(Partial(v));
+            }
+        }
+        if !self.weights.is_empty() {
+            self.weights.resize(self.data.len(), 1.0);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{antimodes, mad, median, mode, modes, quartiles};
+    use super::{antimodes, mad, median, mode, modes, percentile, quartiles};
+    use super::{QuantileMethod, Unsorted};
+    use crate::{Commute, NanPolicy};
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
     #[test]
     fn median_stream() {
@@ -733,6 +1554,53 @@ mod test {
         );
     }
 
+    #[test]
+    fn quartiles_not_hyndman_fan() {
+        let mut four: Unsorted<u32> = vec![1, 2, 3, 4].into_iter().collect();
+        assert_eq!(four.quartiles(), four.quartiles_with(QuantileMethod::Hazen));
+        let mut five: Unsorted<u32> = vec![3, 5, 7, 9, 12].into_iter().collect();
+        assert_eq!(five.quartiles(), Some((4., 7., 10.5)));
+        assert_eq!(
+            five.quartiles_with(QuantileMethod::Hazen),
+            Some((4.5, 7., 9.75))
+        );
+
+        // Every Hyndman-Fan definition differs from `quartiles` for some
+        // length.
+        for method in [
+            QuantileMethod::InvertedCdf,
+            QuantileMethod::AveragedInvertedCdf,
+            QuantileMethod::ClosestObservation,
+            QuantileMethod::InterpolatedInvertedCdf,
+            QuantileMethod::Hazen,
+            QuantileMethod::Weibull,
+            QuantileMethod::Linear,
+            QuantileMethod::MedianUnbiased,
+            QuantileMethod::NormalUnbiased,
+        ] {
+            assert!(
+                (3..12u32).any(|n| {
+                    let mut data: Unsorted<u32> = (0..n).map(|i| i * i).collect();
+                    data.quartiles() != data.quartiles_with(method)
+                }),
+                "{method:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn quartiles_select() {
+        // `quartile_indices` repeats indices for some lengths (e.g. `4`),
+        // which must select the same order statistics as a full sort.
+        for n in 0..40u32 {
+            let data: Vec<u32> = (0..n).map(|i| (i * 7_919) % 41).collect();
+            let mut selected: Unsorted<u32> = data.iter().copied().collect();
+            let mut sorted: Unsorted<u32> = data.into_iter().collect();
+            sorted.sort();
+            assert_eq!(selected.quartiles(), sorted.quartiles(), "{n}");
+        }
+    }
+
     #[test]
     fn quartiles_floats() {
         assert_eq!(
@@ -756,4 +1624,265 @@ mod test {
             Some((5., 9., 20.))
         );
     }
+
+    #[test]
+    fn quantile_methods() {
+        // Matches R's `quantile(1:10, 0.3, type = 1:9)`.
+        let expected = [
+            (QuantileMethod::InvertedCdf, 3.0),
+            (QuantileMethod::AveragedInvertedCdf, 3.5),
+            (QuantileMethod::ClosestObservation, 3.0),
+            (QuantileMethod::InterpolatedInvertedCdf, 3.0),
+            (QuantileMethod::Hazen, 3.5),
+            (QuantileMethod::Weibull, 3.3),
+            (QuantileMethod::Linear, 3.7),
+            (QuantileMethod::MedianUnbiased, 3.433_333_333_333_333),
+            (QuantileMethod::NormalUnbiased, 3.45),
+        ];
+        let mut data: Unsorted<usize> = (1..=10).rev().collect();
+        for (method, want) in expected {
+            let got = data.quantile(0.3, method).unwrap();
+            assert!((got - want).abs() < 1e-12, "{method:?}: {got} != {want}");
+        }
+    }
+
+    #[test]
+    fn quantile_bounds() {
+        let mut data: Unsorted<f64> = vec![4.0, 1.0, 3.0, 2.0].into_iter().collect();
+        assert_eq!(data.quantile(0.0, QuantileMethod::Weibull), Some(1.0));
+        assert_eq!(data.quantile(1.0, QuantileMethod::Weibull), Some(4.0));
+        assert_eq!(data.quantile(0.5, QuantileMethod::Linear), data.median());
+        assert_eq!(data.quantile(1.5, QuantileMethod::Linear), None);
+        assert_eq!(
+            data.quantiles(&[0.0, 0.5, 1.0], QuantileMethod::Linear),
+            Some(vec![1.0, 2.5, 4.0])
+        );
+        assert_eq!(
+            data.quartiles_with(QuantileMethod::Linear),
+            Some((1.75, 2.5, 3.25))
+        );
+        assert_eq!(
+            percentile(
+                vec![1usize, 2, 3, 4, 5].into_iter(),
+                90.0,
+                QuantileMethod::Linear
+            ),
+            Some(4.6)
+        );
+        assert_eq!(
+            Unsorted::<f64>::new().quantile(0.5, QuantileMethod::Linear),
+            None
+        );
+    }
+
+    #[test]
+    fn selection_matches_sort() {
+        let data: Vec<usize> = (0..1_000).map(|i| (i * 7_919) % 1_009).collect();
+        let mut sorted = data.clone();
+        sorted.sort_unstable();
+
+        let mut unsorted: Unsorted<usize> = data.iter().copied().collect();
+        assert_eq!(unsorted.select_nth(0), Some(&sorted[0]));
+        assert_eq!(unsorted.select_nth(500), Some(&sorted[500]));
+        assert_eq!(unsorted.select_nth(1_000), None);
+
+        let qs = [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99];
+        for method in [QuantileMethod::Linear, QuantileMethod::MedianUnbiased] {
+            let mut unsorted: Unsorted<usize> = data.iter().copied().collect();
+            let expected: Vec<f64> = qs
+                .iter()
+                .map(|&q| super::quantile_on_sorted(&sorted, q, method).unwrap())
+                .collect();
+            assert_eq!(unsorted.quantiles(&qs, method), Some(expected));
+        }
+
+        let mut unsorted: Unsorted<usize> = data.iter().copied().collect();
+        assert_eq!(unsorted.median(), super::median_on_sorted(&sorted));
+        let mut unsorted: Unsorted<usize> = data.iter().copied().collect();
+        assert_eq!(unsorted.quartiles(), super::quartiles_on_sorted(&sorted));
+        let mut unsorted: Unsorted<usize> = data.into_iter().collect();
+        assert_eq!(unsorted.mad(None), Some(252.0));
+    }
+
+    #[test]
+    fn weighted_quantiles() {
+        // Integer weights are the same as repeating the elements.
+        let mut weighted = Unsorted::new();
+        for (x, w) in [(5, 2.0), (1, 1.0), (9, 0.0), (3, 3.0), (4, 1.0)] {
+            weighted.add_weighted(x, w);
+        }
+        let mut expanded: Unsorted<usize> = vec![1, 3, 3, 3, 4, 5, 5].into_iter().collect();
+        assert_eq!(weighted.weight(), 7.0);
+        for q in [0.0, 0.1, 0.25, 3.0 / 7.0, 0.5, 0.75, 6.0 / 7.0, 1.0] {
+            assert_eq!(
+                weighted.weighted_quantile(q),
+                expanded.quantile(q, QuantileMethod::AveragedInvertedCdf),
+                "{q}"
+            );
+        }
+        assert_eq!(weighted.weighted_median(), Some(3.0));
+        assert_eq!(weighted.weighted_quantile(1.5), None);
+
+        // Unit weights give the ordinary median, and adding unweighted
+        // elements after weighted ones gives them weight 1.
+        let mut unweighted: Unsorted<f64> = vec![4.0, 1.0, 3.0, 2.0].into_iter().collect();
+        assert_eq!(unweighted.weighted_median(), Some(2.5));
+        unweighted.add_weighted(10.0, 2.0);
+        unweighted.add(0.0);
+        assert_eq!(unweighted.weight(), 7.0);
+        assert_eq!(unweighted.weighted_median(), Some(3.0));
+        assert_eq!(unweighted.median(), Some(2.5));
+        assert_eq!(unweighted.quantile(1.0, QuantileMethod::Linear), Some(10.0));
+
+        // Fractional weights, and merging weighted with unweighted data.
+        let mut a = Unsorted::new();
+        a.add_weighted(1.0, 0.25);
+        a.add_weighted(2.0, 0.25);
+        let b: Unsorted<f64> = vec![3.0].into_iter().collect();
+        a.merge(b);
+        assert_eq!(a.weighted_median(), Some(3.0));
+        assert_eq!(a.weighted_quantile(0.1), Some(1.0));
+        assert_eq!(Unsorted::<f64>::new().weighted_median(), None);
+    }
+
+    #[test]
+    fn robust_location() {
+        let data = [2.0f64, 4.0, 1.0, 8.0, 16.0, 3.0, 5.0, 100.0, 7.0];
+        let mut unsorted: Unsorted<f64> = data.into_iter().collect();
+        let close = |got: Option<f64>, expected: f64| {
+            let got = got.unwrap();
+            assert!((got - expected).abs() < 1e-12, "{got} != {expected}");
+        };
+
+        close(unsorted.trimmed_mean(0.0), data.iter().sum::<f64>() / 9.0);
+        close(unsorted.trimmed_mean(0.2), 6.428_571_428_571_429);
+        close(unsorted.trimmed_mean(0.25), 5.4);
+        assert_eq!(unsorted.trimmed_mean(0.5), None);
+
+        close(unsorted.winsorized_mean(0.2), 7.0);
+        close(unsorted.winsorized_variance(0.2), 30.25);
+        close(unsorted.winsorized_mean(0.25), 5.444_444_444_444_445);
+        close(unsorted.winsorized_variance(0.25), 5.277_777_777_777_778);
+
+        close(unsorted.interquartile_mean(), 5.388_888_888_888_889);
+        let mut twelve: Unsorted<u32> = vec![5, 8, 4, 38, 8, 6, 9, 7, 7, 3, 1, 6]
+            .into_iter()
+            .collect();
+        close(twelve.interquartile_mean(), 6.5);
+
+        close(unsorted.hodges_lehmann(), 6.0);
+        let mut even: Unsorted<f64> = data[..8].iter().copied().collect();
+        close(even.hodges_lehmann(), 5.75);
+        let mut negative: Unsorted<i32> = vec![-3, -1, 0, 7].into_iter().collect();
+        close(negative.hodges_lehmann(), -0.25);
+        for n in [1, 2, 57, 200] {
+            let data: Vec<f64> = (0..n)
+                .map(|i| f64::from((i * 7_919 + 13) % 101) - 50.5)
+                .collect();
+            let mut averages: Vec<f64> = (0..data.len())
+                .flat_map(|i| {
+                    let x = data[i];
+                    data[i..].iter().map(move |&y| (x + y) / 2.0)
+                })
+                .collect();
+            averages.sort_by(f64::total_cmp);
+            let mut unsorted: Unsorted<f64> = data.into_iter().collect();
+            assert_eq!(
+                unsorted.hodges_lehmann(),
+                super::median_on_sorted(&averages)
+            );
+        }
+
+        // Integer beta parameters, checked against the binomial form of the
+        // incomplete beta function.
+        assert!((unsorted.harrell_davis_median().unwrap() - 5.698_410_173_655_013).abs() < 1e-9);
+        assert!(
+            (unsorted.harrell_davis_quantile(0.3).unwrap() - 3.250_329_509_547_442).abs() < 1e-9
+        );
+        assert_eq!(unsorted.harrell_davis_quantile(1.0), None);
+
+        let mut empty: Unsorted<f64> = Unsorted::new();
+        assert_eq!(empty.trimmed_mean(0.1), None);
+        assert_eq!(empty.interquartile_mean(), None);
+        assert_eq!(empty.winsorized_mean(0.1), None);
+        assert_eq!(empty.hodges_lehmann(), None);
+        assert_eq!(empty.harrell_davis_median(), None);
+    }
+
+    #[test]
+    fn nan_policies() {
+        let data = [3.0, f64::NAN, 1.0, 2.0, f64::NAN, 4.0];
+
+        // `NaN`s sort last, so selection and sorting agree.
+        let mut total: Unsorted<f64> = data.iter().copied().collect();
+        assert_eq!(total.nan_count(), 2);
+        assert_eq!(total.median(), Some(3.5));
+        assert_eq!(total.select_nth(3), Some(&4.0));
+        assert!(total.select_nth(5).unwrap().is_nan());
+        assert_eq!(total.quantile(0.5, QuantileMethod::Linear), Some(3.5));
+        assert_eq!(total.mad(None), Some(2.0));
+
+        let mut skip = Unsorted::with_nan_policy(NanPolicy::Skip);
+        skip.extend(data.iter().copied());
+        skip.add(f64::NAN);
+        assert_eq!((skip.len(), skip.nan_count()), (4, 3));
+        assert_eq!(skip.median(), Some(2.5));
+        assert_eq!(skip.mad(None), Some(1.0));
+        assert_eq!(skip.quartiles(), Some((1.5, 2.5, 3.5)));
+
+        let mut propagate = Unsorted::with_nan_policy(NanPolicy::Propagate);
+        propagate.extend(data.iter().copied());
+        assert_eq!(propagate.len(), 6);
+        assert!(propagate.median().unwrap().is_nan());
+        assert!(propagate.quartiles().unwrap().0.is_nan());
+        assert!(propagate.trimmed_mean(0.4).unwrap().is_nan());
+        assert_eq!(propagate.quantile(2.0, QuantileMethod::Linear), None);
+        assert_eq!(
+            propagate.winsorized_variance(0.1).map(f64::is_nan),
+            Some(true)
+        );
+        let mut small = Unsorted::with_nan_policy(NanPolicy::Propagate);
+        small.add(f64::NAN);
+        assert_eq!(small.quartiles(), None);
+
+        // Merging keeps the counts, and an empty state adopts the policy.
+        let mut merged = Unsorted::new();
+        merged.merge(skip);
+        let mut more = Unsorted::with_nan_policy(NanPolicy::Skip);
+        more.extend(vec![f64::NAN, 5.0]);
+        merged.merge(more);
+        assert_eq!(merged.nan_policy(), NanPolicy::Skip);
+        assert_eq!((merged.len(), merged.nan_count()), (5, 4));
+        assert_eq!(merged.median(), Some(3.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "different NaN policies")]
+    fn nan_policy_mismatch() {
+        let mut a: Unsorted<f64> = vec![1.0].into_iter().collect();
+        a.merge(Unsorted::with_nan_policy(NanPolicy::Skip));
+        let mut b = Unsorted::with_nan_policy(NanPolicy::Skip);
+        b.add(2.0);
+        a.merge(b);
+    }
+
+    #[test]
+    fn parallel() {
+        let mut unsorted: Unsorted<u32> = (0..10_001u32)
+            .into_par_iter()
+            .map(|i| (i * 7_919) % 10_007)
+            .collect();
+        assert_eq!(unsorted.len(), 10_001);
+        let mut sequential: Unsorted<u32> = (0..10_001u32).map(|i| (i * 7_919) % 10_007).collect();
+        assert_eq!(unsorted.median(), sequential.median());
+        assert_eq!(unsorted.quartiles(), sequential.quartiles());
+
+        let data: Vec<f64> = (0..40_000)
+            .map(|i| f64::from((i * 7_919) % 10_007))
+            .collect();
+        let mut from_slice = Unsorted::par_from_slice(&data);
+        let mut sequential: Unsorted<f64> = data.into_iter().collect();
+        assert_eq!(from_slice.len(), 40_000);
+        assert_eq!(from_slice.median(), sequential.median());
+    }
 }